@@ -0,0 +1,173 @@
+//! Time and peak memory of packing large synthetic textures, run with `cargo bench`
+//!
+//! Packing streams textures from disk, large pngs are decoded row by row and the large bmp is
+//! only checked against its header. The buffered run reads every texture into memory and
+//! writes it from there like packing did before, with the same compression. Many textures are
+//! hashed and compressed in parallel, `RAYON_NUM_THREADS=1` shows the sequential time.
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+	fs::File,
+	io::{Read, Write},
+	path::Path,
+	time::Instant,
+};
+
+use common::*;
+use kspacker_core::{texture, CompressionProfile, ImageFormat, PackOptions, PackablePreset, Packer, TextureType};
+use serde_json::json;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Saves a preset using an 8k by 4k bmp and a 4k png sprite sheet
+fn setup(ks: &FakeKeysight) -> u64 {
+	let large = image::RgbImage::from_fn(8192, 4096, |x, y| image::Rgb([x as u8, y as u8, 0]));
+	let mut bmp = Vec::new();
+	large.write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageFormat::Bmp).unwrap();
+	drop(large);
+
+	let sheet = image::RgbaImage::from_fn(4096, 4096, |x, y| image::Rgba([(x / 256) as u8, (y / 256) as u8, 0, 255]));
+	let mut png = Vec::new();
+	sheet.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+	drop(sheet);
+
+	ks.add_texture(false, TextureType::Diffuse, "large.bmp", &bmp);
+	ks.add_texture(false, TextureType::NoteShape, "sheet.png", &png);
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("large");
+	let note_objects = &mut preset["effects"]["noteObjects"];
+	note_objects["noteObjectsEnabled"] = json!(true);
+	note_objects["noteObjectShape"] = json!("sheet");
+	ks.save_preset("large", &preset);
+
+	(bmp.len() + png.len()) as u64
+}
+
+/// Presets of the bundle benchmark, each with a texture in four fields of every material
+const BUNDLE_PRESETS: u32 = 6;
+
+/// Saves the presets of a bundle using 216 distinct textures
+fn setup_bundle(ks: &FakeKeysight) -> (Vec<String>, u64) {
+	const FIELDS: [(&str, &str, TextureType); 4] = [
+		("diffuseUseTexture", "diffuseTexture", TextureType::Diffuse),
+		("normalOn", "normalTexture", TextureType::Normal),
+		("maskOn", "maskTexture", TextureType::Mask),
+		("specularUseTexture", "specularTexture", TextureType::Specular),
+	];
+	let materials: Vec<&str> = kspacker_core::references::TEXTURE_FIELDS
+		.iter()
+		.filter_map(|field| field.path.strip_suffix(".diffuseTexture"))
+		.collect();
+
+	let mut size = 0;
+	let mut idx = 0u32;
+	let mut presets = Vec::new();
+	for preset_idx in 0..BUNDLE_PRESETS {
+		let mut preset = preset_fixture();
+		for material in &materials {
+			for (flag, field, typ) in FIELDS {
+				let image = image::RgbImage::from_fn(512, 256, |x, y| image::Rgb([x as u8, y as u8, idx as u8]));
+				let mut bmp = Vec::new();
+				image.write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageFormat::Bmp).unwrap();
+				let name = format!("texture{}", idx);
+				ks.add_texture(false, typ, &format!("{}.bmp", name), &bmp);
+				size += bmp.len() as u64;
+				idx += 1;
+
+				let material = preset.pointer_mut(&format!("/{}", material.replace('.', "/"))).unwrap();
+				material[flag] = json!(true);
+				material[field] = json!(name);
+			}
+		}
+
+		let name = format!("bundled{}", preset_idx);
+		ks.save_preset(&name, &preset);
+		presets.push(name);
+	}
+	(presets, size)
+}
+
+/// Runs `pack` and prints its time, its peak memory and the size of the package at `path`
+fn measure(label: &str, path: &Path, pack: impl FnOnce()) {
+	let start = Instant::now();
+	let ((), peak) = PeakAlloc::measure(pack);
+	let elapsed = start.elapsed();
+	println!(
+		"{:<12} {:>8.2} s {:>10.1} MiB peak {:>10.1} MiB written",
+		label,
+		elapsed.as_secs_f64(),
+		peak as f64 / MIB,
+		std::fs::metadata(path).unwrap().len() as f64 / MIB
+	);
+}
+
+fn bench(ks: &FakeKeysight, presets: &[String], label: &str, options: &PackOptions) {
+	let packable: Vec<PackablePreset> =
+		presets.iter().map(|name| Packer::new(ks.env.clone(), 0, name).collect(false).unwrap()).collect();
+	let path = ks.pack_path(label);
+	let extra_meta = extra_meta();
+
+	measure(label, &path, || {
+		match packable.as_slice() {
+			[preset] => preset.pack(&path, extra_meta, options),
+			_ => PackablePreset::pack_bundle(&packable, &path, extra_meta, options),
+		}
+		.unwrap();
+	});
+}
+
+/// Packs the assets and the preset like packing did before streaming: every texture is read
+/// into memory, decoded from there and written with `write_all`, using the entry options of
+/// [`CompressionProfile::Balanced`]
+fn bench_buffered(ks: &FakeKeysight, preset: &str, label: &str) {
+	let packable = Packer::new(ks.env.clone(), 0, preset).collect(false).unwrap();
+	let path = ks.pack_path(label);
+	let compressed =
+		FileOptions::default().compression_method(CompressionMethod::Zstd).compression_level(Some(9)).large_file(false);
+	let stored = FileOptions::default().compression_method(CompressionMethod::Stored).large_file(false);
+
+	measure(label, &path, || {
+		let mut zipfile = ZipWriter::new(File::create(&path).unwrap());
+		zipfile.add_directory("assets", compressed).unwrap();
+		for asset in packable.assets() {
+			let mut data = Vec::new();
+			File::open(&asset.path).unwrap().read_to_end(&mut data).unwrap();
+			let hash = blake3::hash(&data);
+			let format = ImageFormat::detect(&data, &asset.ext);
+			texture::decode(&data, format).unwrap();
+
+			// incompressible textures are stored, as the packer does
+			let sample = &data[..data.len().min(64 * 1024)];
+			let saving = 1.0 - zstd::bulk::compress(sample, 1).unwrap().len() as f64 / sample.len() as f64;
+			let options = if saving >= 0.05 { compressed } else { stored };
+			zipfile.start_file(format!("assets/{}", hash.to_hex()), options).unwrap();
+			zipfile.write_all(&data).unwrap();
+		}
+		zipfile.start_file(format!("{}.json", preset), compressed).unwrap();
+		zipfile.write_all(&std::fs::read(ks.env.custom_preset_dir().join(format!("{}.json", preset))).unwrap()).unwrap();
+		zipfile.finish().unwrap();
+	});
+}
+
+fn main() {
+	let ks = FakeKeysight::new();
+	let size = setup(&ks);
+	println!("packing {:.1} MiB of textures", size as f64 / MIB);
+
+	let large = [String::from("large")];
+	bench(&ks, &large, "streaming", &PackOptions::default());
+	bench_buffered(&ks, "large", "buffered");
+
+	let (presets, size) = setup_bundle(&ks);
+	println!("packing {} presets with {:.1} MiB of textures", presets.len(), size as f64 / MIB);
+	bench(&ks, &presets, "bundle", &PackOptions::default());
+	bench(&ks, &presets, "bundle, max", &PackOptions { compression: CompressionProfile::Max, ..PackOptions::default() });
+}
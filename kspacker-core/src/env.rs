@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+use super::helpers;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum EnvError {
+	#[error("no local data directory")]
+	#[diagnostic(
+		code(env::data_dir::missing),
+		help("The local data directory of this system could not be determined, please set it explicitly")
+	)]
+	NoDataDir,
+}
+
+/// The directories keysight reads presets and textures from
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeysightEnv {
+	install_root: PathBuf,
+	data_root:    PathBuf,
+}
+
+impl KeysightEnv {
+	/// `install_root` is the keysight installation directory, `data_root` the local app data
+	/// directory that contains `Keysight/Saved`
+	pub fn new(install_root: impl Into<PathBuf>, data_root: impl Into<PathBuf>) -> Self {
+		Self { install_root: install_root.into(), data_root: data_root.into() }
+	}
+
+	/// Uses the local data directory of the current user as the data root
+	pub fn with_default_data(install_root: impl Into<PathBuf>) -> Result<Self, EnvError> {
+		let data_root = helpers::default_data_dir().ok_or(EnvError::NoDataDir)?;
+		Ok(Self::new(install_root, data_root))
+	}
+
+	pub fn install_root(&self) -> &Path { &self.install_root }
+
+	pub fn data_root(&self) -> &Path { &self.data_root }
+
+	pub fn root_preset_dir(&self) -> PathBuf { helpers::root_preset_dir(&self.install_root) }
+
+	pub fn root_asset_dir(&self) -> PathBuf { helpers::root_asset_dir(&self.install_root) }
+
+	pub fn saved_dir(&self) -> PathBuf { helpers::saved_dir(&self.data_root) }
+
+	pub fn registry_file(&self) -> PathBuf { helpers::registry_file(&self.data_root) }
+
+	pub fn custom_preset_dir(&self) -> PathBuf { helpers::custom_preset_dir(&self.data_root) }
+
+	pub fn custom_asset_dir(&self, random: bool) -> PathBuf {
+		helpers::custom_asset_dir(&self.data_root, random)
+	}
+}
@@ -0,0 +1,138 @@
+//! Versioning of the package layout
+//!
+//! Every package records the format version it was written with in `metadata.json`.
+//! Packages written before the field existed are read as version 0 and upgraded. The version
+//! only changes when older readers can no longer install a package correctly, fields that can
+//! safely be ignored are added without a new version.
+use chrono::{DateTime, Utc};
+
+use super::{unpacker::UnpackError, BundleMetaData, MetaEntry, PackMetaData, Version};
+
+pub type FormatVersion = u32;
+
+/// The format of packages containing a single preset
+pub const FORMAT_VERSION: FormatVersion = 1;
+
+/// The oldest kspacker release able to read [`FORMAT_VERSION`]
+pub const MIN_READER_VERSION: &str = "0.1.0";
+
+/// The format of bundles containing several presets
+pub const BUNDLE_FORMAT_VERSION: FormatVersion = 2;
+
+/// The first kspacker release able to read bundles
+pub const BUNDLE_MIN_READER_VERSION: &str = "0.2.0";
+
+/// The kspacker release this reader ships in, packages requiring a later one are rejected.
+/// Kept apart from the crate versions, it is raised together with the `*_MIN_READER_VERSION`
+/// of a new format and must match the release of the application that first reads it.
+pub const READER_VERSION: &str = "0.2.0";
+
+pub const METADATA_ENTRY: &str = "metadata.json";
+
+/// The preset of a single preset package
+pub const PRESET_ENTRY: &str = "preset.json";
+
+/// The entry the preset at `idx` of a bundle is stored as
+pub fn bundle_preset_entry(idx: usize) -> String { format!("presets/{}.json", idx) }
+
+/// The metadata of either kind of package
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PackageMetaData {
+	Preset(PackMetaData),
+	Bundle(BundleMetaData),
+}
+
+/// The fields every format has, used to pick the reader
+#[derive(serde::Deserialize)]
+struct Probe {
+	#[serde(default)]
+	format_version: FormatVersion,
+	#[serde(default)]
+	requires:       Option<String>,
+}
+
+/// Metadata of packages written before the format version was recorded
+#[derive(serde::Deserialize)]
+struct LegacyMetaData {
+	name:           String,
+	author:         String,
+	description:    String,
+	packed:         DateTime<Utc>,
+	preset_version: Version,
+	target_version: Version,
+	assets:         Vec<MetaEntry>,
+}
+
+impl From<LegacyMetaData> for PackMetaData {
+	fn from(legacy: LegacyMetaData) -> Self {
+		Self {
+			format_version: FORMAT_VERSION,
+			requires:       MIN_READER_VERSION.to_owned(),
+			name:           legacy.name,
+			author:         legacy.author,
+			description:    legacy.description,
+			packed:         legacy.packed,
+			preset_version: legacy.preset_version,
+			target_version: legacy.target_version,
+			assets:         legacy.assets,
+		}
+	}
+}
+
+/// The latest of `minimum` and `releases`, which a package made of parts requiring each of them
+/// requires
+pub fn latest_release<'a>(minimum: &'a str, releases: impl IntoIterator<Item = &'a str>) -> &'a str {
+	releases.into_iter().fold(minimum, |latest, release| {
+		if parse_release(release) > parse_release(latest) {
+			release
+		} else {
+			latest
+		}
+	})
+}
+
+/// The oldest release able to read a package in [`FORMAT_VERSION`] containing `assets`
+pub fn min_reader_version(assets: &[MetaEntry]) -> &'static str {
+	latest_release(MIN_READER_VERSION, assets.iter().map(|asset| asset.texture_type.min_reader_version()))
+}
+
+/// Splits a `major.minor.patch` release into its numbers, missing numbers are 0
+fn parse_release(release: &str) -> Option<[u64; 3]> {
+	let mut numbers = [0; 3];
+	for (idx, part) in release.trim().split('.').enumerate() {
+		*numbers.get_mut(idx)? = part.parse().ok()?;
+	}
+	Some(numbers)
+}
+
+/// Whether this reader is at least the release `requires`, which it cannot tell for malformed
+/// releases
+pub fn is_supported(requires: &str) -> bool {
+	match (parse_release(requires), parse_release(READER_VERSION)) {
+		(Some(required), Some(reader)) => required <= reader,
+		_ => false,
+	}
+}
+
+/// Parses `metadata.json` with the reader for its format version, upgrading older formats
+pub fn read_metadata(json: &[u8]) -> Result<PackageMetaData, UnpackError> {
+	let json_error = |reason| UnpackError::JsonError { reason };
+	let probe: Probe = serde_json::from_slice(json).map_err(json_error)?;
+
+	if probe.requires.as_deref().is_some_and(|requires| !is_supported(requires)) {
+		return Err(UnpackError::UnsupportedFormat { version: probe.format_version, requires: probe.requires });
+	}
+
+	match probe.format_version {
+		0 => {
+			debug!("upgrading legacy package metadata");
+			let legacy: LegacyMetaData = serde_json::from_slice(json).map_err(json_error)?;
+			Ok(PackageMetaData::Preset(legacy.into()))
+		},
+		FORMAT_VERSION => serde_json::from_slice(json).map(PackageMetaData::Preset).map_err(json_error),
+		BUNDLE_FORMAT_VERSION => {
+			serde_json::from_slice(json).map(PackageMetaData::Bundle).map_err(json_error)
+		},
+		version => Err(UnpackError::UnsupportedFormat { version, requires: probe.requires }),
+	}
+}
@@ -0,0 +1,89 @@
+use std::{
+	fs,
+	io,
+	path::{Path, PathBuf},
+};
+
+use super::{KeysightEnv, Version};
+
+pub fn root_preset_dir(install_path: impl AsRef<Path>) -> PathBuf {
+	install_path.as_ref().join("Keysight").join("Default presets").join("Standard")
+}
+
+/// The default preset every installation ships with, used to detect the keysight version
+pub fn default_preset_file(install_path: impl AsRef<Path>) -> PathBuf {
+	root_preset_dir(install_path).join("Plain (default).json")
+}
+
+pub fn root_asset_dir(install_path: impl AsRef<Path>) -> PathBuf {
+	install_path.as_ref().join("Keysight").join("Default textures")
+}
+
+/// The directory keysight saves custom presets and textures in
+pub fn saved_dir(data_root: impl AsRef<Path>) -> PathBuf {
+	data_root.as_ref().join("Keysight").join("Saved")
+}
+
+/// The registry of imported packs, see [`crate::registry::Registry`]
+pub fn registry_file(data_root: impl AsRef<Path>) -> PathBuf {
+	saved_dir(data_root).join("kspacker-installed.json")
+}
+
+pub fn custom_preset_dir(data_root: impl AsRef<Path>) -> PathBuf {
+	saved_dir(data_root).join("Presets")
+}
+
+pub fn custom_asset_dir(data_root: impl AsRef<Path>, random: bool) -> PathBuf {
+	saved_dir(data_root).join(if random { "Textures (randomizer enabled)" } else { "Textures" })
+}
+
+/// The local data directory keysight saves to, if it can be determined
+pub fn default_data_dir() -> Option<PathBuf> {
+	#[cfg(feature = "proton-steam-comptime")]
+	{
+		Some(std::path::PathBuf::from(env!("PROTON_PATH_OVR")))
+	}
+	#[cfg(not(feature = "proton-steam-comptime"))]
+	{
+		dirs::data_local_dir()
+	}
+}
+
+pub fn maybe_format_version(version: Option<Version>) -> String {
+	if let Some(ver) = version {
+		format!("{:#X}", ver)
+	} else {
+		String::from("Unknown")
+	}
+}
+
+/// Formats a byte count with a binary unit, for showing file sizes
+pub fn format_size(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", size, UNITS[unit])
+	}
+}
+
+pub fn list_all_presets(env: &KeysightEnv) -> io::Result<Vec<String>> {
+	let mut presets = Vec::new();
+	for f in fs::read_dir(env.custom_preset_dir())? {
+		let file = f?;
+		if file.metadata()?.is_file() {
+			if let Some(name) = file.path().file_stem() {
+				presets.push(name.to_string_lossy().to_string());
+			}
+		}
+	}
+
+	Ok(presets)
+}
@@ -1069,73 +1069,3 @@ pub struct WispElement {
 	#[serde(rename = "edgeAmount")]
 	pub edge_amount:                f64,
 }
-
-pub trait Texturable {
-	fn diffuse(&self) -> Option<&str>;
-	fn emissive(&self) -> Option<&str>;
-	fn mask(&self) -> Option<&str>;
-	fn metalness(&self) -> Option<&str>;
-	fn normal(&self) -> Option<&str>;
-	fn roughness(&self) -> Option<&str>;
-	fn specular(&self) -> Option<&str>;
-}
-
-macro_rules! impl_texturable {
-    ($($t:ty)*) => {
-        $(
-        impl crate::pack::ks_preset::Texturable for $t {
-            fn diffuse(&self) -> Option<&str> {
-                if self.diffuse_use_texture {
-                    Some(&self.diffuse_texture)
-                } else {
-                    None
-                }
-            }
-            fn emissive(&self) -> Option<&str> {
-                if self.emissive_use_texture {
-                    Some(&self.emissive_texture)
-                } else {
-                    None
-                }
-            }
-            fn mask(&self) -> Option<&str> {
-                if self.mask_on {
-                    Some(&self.mask_texture)
-                } else {
-                    None
-                }
-            }
-            fn metalness(&self) -> Option<&str> {
-                if self.metalness_use_texture {
-                    Some(&self.metalness_texture)
-                } else {
-                    None
-                }
-            }
-            fn normal(&self) -> Option<&str> {
-                if self.normal_on {
-                    Some(&self.normal_texture)
-                } else {
-                    None
-                }
-            }
-            fn roughness(&self) -> Option<&str> {
-                if self.roughness_use_texture {
-                    Some(&self.roughness_texture)
-                } else {
-                    None
-                }
-            }
-            fn specular(&self) -> Option<&str> {
-                if self.specular_use_texture {
-                    Some(&self.roughness_texture)
-                } else {
-                    None
-                }
-            }
-        }
-    )*
-    }
-}
-
-impl_texturable!(Material);
@@ -0,0 +1,263 @@
+//! The preset packing engine behind kspacker.
+//!
+//! [`Packer`] collects a saved preset and the custom assets it references into a
+//! [`PackablePreset`], which can then be written to a `.kspreset` archive. The
+//! [`Unpacker`] loads such an archive into a [`PackedFile`] that can be inspected and
+//! installed.
+#[macro_use]
+extern crate tracing;
+
+use std::{fs::File, path::Path};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+
+mod env;
+pub mod format;
+pub mod helpers;
+pub mod ks_preset;
+pub mod limits;
+pub mod packer;
+pub mod progress;
+pub mod references;
+pub mod registry;
+pub mod sanitize;
+pub mod steam;
+pub mod texture;
+mod transaction;
+pub mod unpacker;
+
+pub use env::{EnvError, KeysightEnv};
+pub use ks_preset::KeysightPresetElement;
+pub use limits::UnpackLimits;
+pub use packer::{
+	CollectPolicy,
+	CompressionProfile,
+	ExtraMeta,
+	FoundAsset,
+	PackError,
+	PackOptions,
+	PackSummary,
+	PackWarning,
+	PackablePreset,
+	Packer,
+};
+pub use progress::{Progress, ProgressUpdate};
+pub use registry::{InstalledPack, Registry, RegistryError};
+pub use texture::{ImageFormat, ImageInfo, TextureWarning};
+pub use unpacker::{
+	AssetStatus,
+	Conflict,
+	ConflictReport,
+	PackedBundle,
+	PackedFile,
+	Resolution,
+	UnpackError,
+	Unpacker,
+};
+
+pub type Version = u32;
+
+/// Reads the keysight version from the default presets of the installation at `root`
+pub fn get_ks_version(root: impl AsRef<Path>) -> anyhow::Result<Version> {
+	let path = helpers::default_preset_file(root);
+	if !path.exists() {
+		anyhow::bail!("unable to find keysight default presets, please recheck your path");
+	}
+
+	#[derive(serde::Deserialize)]
+	struct KSPresetVersion {
+		#[serde(rename = "versionForUpdatePurposes")]
+		pub version_for_update_purposes: Version,
+	}
+
+	let data: KSPresetVersion =
+		serde_json::from_reader(File::open(path).context("failed to open default preset file")?)
+			.context("failed to load default preset file")?;
+
+	Ok(data.version_for_update_purposes)
+}
+
+/// The metadata for the zip file
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct PackMetaData {
+	/// Layout of the package, see [`format`]
+	pub format_version: format::FormatVersion,
+	/// Oldest kspacker release able to read the package
+	pub requires:       String,
+	pub name:           String,
+	pub author:         String,
+	pub description:    String,
+	pub packed:         DateTime<Utc>,
+	pub preset_version: Version,
+	pub target_version: Version,
+
+	pub assets: Vec<MetaEntry>,
+}
+
+impl PackMetaData {
+	/// Metadata of a preset packed now, in the current format
+	pub fn new(
+		name: impl Into<String>,
+		author: impl Into<String>,
+		description: impl Into<String>,
+		preset_version: Version,
+		target_version: Version,
+		assets: Vec<MetaEntry>,
+	) -> Self {
+		Self {
+			format_version: format::FORMAT_VERSION,
+			requires: format::min_reader_version(&assets).to_owned(),
+			name: name.into(),
+			author: author.into(),
+			description: description.into(),
+			packed: Utc::now(),
+			preset_version,
+			target_version,
+			assets,
+		}
+	}
+}
+
+/// The metadata of a bundle of several presets
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BundleMetaData {
+	pub format_version: format::FormatVersion,
+	pub requires:       String,
+	#[serde(flatten)]
+	pub info:           BundleInfo,
+	pub target_version: Version,
+
+	pub presets: Vec<BundleEntry>,
+}
+
+/// Describes a bundle, or the single preset of a package
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BundleInfo {
+	pub name:        String,
+	pub author:      String,
+	pub description: String,
+	pub packed:      DateTime<Utc>,
+}
+
+/// A preset in a bundle
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BundleEntry {
+	/// The archive entry of the preset
+	pub file:     String,
+	pub metadata: PackMetaData,
+}
+
+/// A single asset packed
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct MetaEntry {
+	pub hash:              String,
+	pub name:              String,
+	pub extension:         String,
+	pub texture_type:      TextureType, // TODO: Add correct type
+	pub source_was_random: bool,
+	/// Format detected from the contents when packing
+	#[serde(default)]
+	pub format:            texture::ImageFormat,
+	/// Properties of the texture, missing in packages made before they were recorded
+	#[serde(default)]
+	pub image:             Option<texture::ImageInfo>,
+	/// Hash of the texture before it was optimised, if optimising changed it
+	#[serde(default)]
+	pub original_hash:     Option<String>,
+}
+
+impl MetaEntry {
+	/// An asset of unknown format and properties, which was stored as it was found
+	pub fn new(
+		hash: impl Into<String>,
+		name: impl Into<String>,
+		extension: impl Into<String>,
+		texture_type: TextureType,
+		source_was_random: bool,
+	) -> Self {
+		Self {
+			hash: hash.into(),
+			name: name.into(),
+			extension: extension.into(),
+			texture_type,
+			source_was_random,
+			format: texture::ImageFormat::Unknown,
+			image: None,
+			original_hash: None,
+		}
+	}
+}
+
+/// Describes a Texture source for the given type. Releases that do not know a type cannot read
+/// packages using it, so every new type needs a [`TextureType::min_reader_version`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum TextureType {
+	Diffuse,
+	Emissive,
+	WorldStencil,
+	Mask,
+	Metalness,
+	Normal,
+	Roughness,
+	Shape,
+	Specular,
+	Stencil,
+	Displacement,
+	NoteShape,
+	NoteBorder,
+}
+
+impl TextureType {
+	pub const ALL: [TextureType; 13] = [
+		TextureType::Diffuse,
+		TextureType::Emissive,
+		TextureType::WorldStencil,
+		TextureType::Mask,
+		TextureType::Metalness,
+		TextureType::Normal,
+		TextureType::Roughness,
+		TextureType::Shape,
+		TextureType::Specular,
+		TextureType::Stencil,
+		TextureType::Displacement,
+		TextureType::NoteShape,
+		TextureType::NoteBorder,
+	];
+
+	/// The first kspacker release able to read packages containing this type, which is also the
+	/// release adding it. [`format::READER_VERSION`] must be at least as new.
+	pub fn min_reader_version(&self) -> &'static str {
+		match *self {
+			TextureType::Diffuse
+			| TextureType::Emissive
+			| TextureType::WorldStencil
+			| TextureType::Mask
+			| TextureType::Metalness
+			| TextureType::Normal
+			| TextureType::Roughness
+			| TextureType::Shape
+			| TextureType::Specular
+			| TextureType::Stencil => format::MIN_READER_VERSION,
+			TextureType::Displacement | TextureType::NoteShape | TextureType::NoteBorder => "0.2.0",
+		}
+	}
+
+	pub fn path_name(&self) -> &'static str {
+		match *self {
+			TextureType::Diffuse | TextureType::Emissive => "Colour",
+			TextureType::WorldStencil | TextureType::Mask => "Mask",
+			TextureType::Metalness => "Metal",
+			TextureType::Normal => "Normal",
+			TextureType::Roughness => "Roughness",
+			TextureType::Shape => "Particle stencil",
+			TextureType::Specular => "Specular",
+			TextureType::Stencil => "Pulse stencil",
+			TextureType::Displacement => "Displacement",
+			TextureType::NoteShape => "Note shape",
+			TextureType::NoteBorder => "Note border",
+		}
+	}
+}
@@ -0,0 +1,126 @@
+//! Resource limits applied when reading untrusted packages
+use std::{
+	fmt,
+	io::{self, Read, Write},
+};
+
+use super::unpacker::UnpackError;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Entries smaller than this are not subject to the compression ratio limit, as small json
+/// files routinely compress well
+pub const RATIO_THRESHOLD: u64 = MIB;
+
+/// Caps on the size of a package and its entries
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct UnpackLimits {
+	/// Uncompressed size of all entries together
+	pub max_total_size:        u64,
+	/// Uncompressed size of a single asset
+	pub max_asset_size:        u64,
+	/// Number of entries in the archive
+	pub max_entries:           usize,
+	/// Uncompressed size divided by compressed size of a single entry
+	pub max_compression_ratio: u64,
+	/// Uncompressed size of `metadata.json` and each preset
+	pub max_json_size:         u64,
+}
+
+impl Default for UnpackLimits {
+	fn default() -> Self {
+		Self {
+			max_total_size:        2048 * MIB,
+			max_asset_size:        256 * MIB,
+			max_entries:           4096,
+			max_compression_ratio: 250,
+			max_json_size:         16 * MIB,
+		}
+	}
+}
+
+/// The limit a package exceeded
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Limit {
+	TotalSize,
+	AssetSize,
+	Entries,
+	CompressionRatio,
+	JsonSize,
+}
+
+impl fmt::Display for Limit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Limit::TotalSize => "total size",
+			Limit::AssetSize => "asset size",
+			Limit::Entries => "entry count",
+			Limit::CompressionRatio => "compression ratio",
+			Limit::JsonSize => "json size",
+		})
+	}
+}
+
+impl UnpackLimits {
+	fn is_json(name: &str) -> bool { name.ends_with(".json") }
+
+	fn exceeded(entry: &str, limit: Limit, max: u64) -> UnpackError {
+		UnpackError::LimitExceeded { entry: entry.to_owned(), limit, max }
+	}
+
+	/// The most bytes the entry `name` may decompress to
+	fn entry_max(&self, name: &str, compressed: u64) -> u64 {
+		let size_max = if Self::is_json(name) { self.max_json_size } else { self.max_asset_size };
+		let ratio_max = compressed.saturating_mul(self.max_compression_ratio).max(RATIO_THRESHOLD);
+		size_max.min(ratio_max)
+	}
+
+	pub fn check_entries(&self, entries: usize) -> Result<(), UnpackError> {
+		if entries > self.max_entries {
+			return Err(Self::exceeded("package", Limit::Entries, self.max_entries as u64));
+		}
+
+		Ok(())
+	}
+
+	pub fn check_total(&self, total: u64) -> Result<(), UnpackError> {
+		if total > self.max_total_size {
+			return Err(Self::exceeded("package", Limit::TotalSize, self.max_total_size));
+		}
+
+		Ok(())
+	}
+
+	/// Checks the uncompressed `size` of the entry `name`
+	pub fn check_entry(&self, name: &str, size: u64, compressed: u64) -> Result<(), UnpackError> {
+		if Self::is_json(name) && size > self.max_json_size {
+			return Err(Self::exceeded(name, Limit::JsonSize, self.max_json_size));
+		}
+
+		if !Self::is_json(name) && size > self.max_asset_size {
+			return Err(Self::exceeded(name, Limit::AssetSize, self.max_asset_size));
+		}
+
+		if size > RATIO_THRESHOLD && size > compressed.saturating_mul(self.max_compression_ratio) {
+			return Err(Self::exceeded(name, Limit::CompressionRatio, self.max_compression_ratio));
+		}
+
+		Ok(())
+	}
+
+	/// Copies the entry `name` from `src` to `dst`, stopping as soon as it exceeds its limits
+	/// instead of trusting the size declared in the archive
+	pub fn copy_entry(
+		&self,
+		name: &str,
+		compressed: u64,
+		src: &mut impl Read,
+		dst: &mut impl Write,
+	) -> Result<u64, UnpackError> {
+		let max = self.entry_max(name, compressed);
+		let copied = io::copy(&mut src.take(max.saturating_add(1)), dst)
+			.map_err(|reason| UnpackError::PackIOError { reason })?;
+		self.check_entry(name, copied, compressed)?;
+		Ok(copied)
+	}
+}
@@ -0,0 +1,879 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt,
+	fs::File,
+	io::{self, BufRead, BufReader, Read, Seek, Write},
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use rayon::prelude::*;
+use tempfile::{NamedTempFile, SpooledTempFile};
+use zip::{result::ZipResult, write::FileOptions, ZipArchive, ZipWriter};
+
+use super::{
+	format,
+	progress::{Progress, Tracker},
+	references,
+	texture::{self, ImageFormat, ImageInfo, Optimise, TextureWarning},
+	BundleEntry,
+	BundleInfo,
+	BundleMetaData,
+	KeysightEnv,
+	MetaEntry,
+	PackMetaData,
+	TextureType,
+	Version,
+};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum PackError {
+	#[error("preset `{name}` not found")]
+	#[diagnostic(
+		code(pack::preset::not_found),
+		help("Make shure you have not renamed or deleted the preset after starting the program.")
+	)]
+	NotFound { name: String },
+
+	#[error("cannot read preset")]
+	#[diagnostic(code(pack::preset::unreadable))]
+	Unreadable {
+		#[source]
+		reason: io::Error,
+	},
+
+	#[error("malformed preset")]
+	#[diagnostic(code(pack::preset::malformed))]
+	MalformedPreset {
+		#[source]
+		reason: serde_json::Error,
+	},
+
+	#[error("builtin preset")]
+	#[diagnostic(
+		code(pack::preset::builtin),
+		help("The preset is a builtin preset, and no override was set")
+	)]
+	IsBuiltin,
+
+	#[error("cannot create output file")]
+	#[diagnostic(code(pack::pack::io))]
+	PackIoError {
+		#[source]
+		reason: io::Error,
+	},
+
+	#[error("error while zipping")]
+	#[diagnostic(code(pack::pack::zip))]
+	ZipError {
+		#[source]
+		reason: zip::result::ZipError,
+	},
+
+	#[error("malformed metadata")]
+	#[diagnostic(code(pack::meta::malformed))]
+	MalformedMeta {
+		#[source]
+		reason: serde_json::Error,
+	},
+
+	#[error("referenced assets not found: {}", names.join(", "))]
+	#[diagnostic(
+		code(pack::preset::missing_assets),
+		help("The preset uses textures that were renamed or deleted, restore them or change the preset")
+	)]
+	MissingAssets { names: Vec<String> },
+
+	#[error("cannot decode texture {}", path.display())]
+	#[diagnostic(
+		code(pack::asset::invalid),
+		help("The file is truncated, corrupt or not an image, fix or replace it")
+	)]
+	InvalidTexture {
+		path:   PathBuf,
+		#[source]
+		reason: image::ImageError,
+	},
+
+	#[error("packing was cancelled")]
+	#[diagnostic(code(pack::cancelled), help("The incomplete output file has been removed"))]
+	Cancelled,
+
+	#[error("wrong version")]
+	#[diagnostic(code(pack::meta::invalid_version))]
+	WrongVersion {
+		wanted: Version,
+		got: Version
+	},
+}
+
+/// Which of the textures a preset references are collected
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CollectPolicy {
+	/// Only textures the preset currently uses
+	#[default]
+	EnabledOnly,
+	/// Also textures of disabled particles, pulses and material slots, so they still work when
+	/// the recipient enables them
+	AllReferenced,
+}
+
+impl CollectPolicy {
+	pub const ALL: [CollectPolicy; 2] = [CollectPolicy::EnabledOnly, CollectPolicy::AllReferenced];
+}
+
+impl fmt::Display for CollectPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			CollectPolicy::EnabledOnly => "Enabled textures only",
+			CollectPolicy::AllReferenced => "All referenced textures",
+		})
+	}
+}
+
+/// Collects a saved preset and the assets it references
+#[derive(Debug)]
+pub struct Packer {
+	env:    KeysightEnv,
+	preset: String,
+	ksv: Version,
+	policy: CollectPolicy,
+	strict: bool,
+	extensions: Vec<String>,
+}
+
+impl Packer {
+	pub fn new(env: KeysightEnv, ksv: Version, preset: impl Into<String>) -> Self {
+		Packer {
+			env,
+			ksv,
+			preset: preset.into(),
+			policy: CollectPolicy::default(),
+			strict: false,
+			extensions: texture::DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+		}
+	}
+
+	/// Sets the extensions probed for each texture, in order of preference, see
+	/// [`texture::DEFAULT_EXTENSIONS`]
+	pub fn extensions<S: Into<String>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+		self.extensions = extensions.into_iter().map(Into::into).collect();
+		self
+	}
+
+	/// Fails collecting if a referenced texture is neither a builtin nor a custom asset, instead
+	/// of packing the preset without it
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// Sets which referenced textures are collected, only enabled ones by default
+	pub fn policy(mut self, policy: CollectPolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	/// Loads the preset and resolves all referenced assets
+	#[instrument(skip(self))]
+	pub fn collect(&self, allow_builtin: bool) -> Result<PackablePreset, PackError> {
+		if !allow_builtin && self.check_builtin_preset() {
+			return Err(PackError::IsBuiltin);
+		}
+
+		info!("discovering assets");
+		let preset_path = self.env.custom_preset_dir().join(format!("{}.json", self.preset));
+		if !preset_path.exists() {
+			warn!(preset_path=%preset_path.display(), "preset does not exist");
+			return Err(PackError::NotFound { name: self.preset.clone() });
+		}
+
+		let ks_version = {
+			#[derive(Debug, serde::Deserialize)]
+			struct KsVersionOnly {
+				#[serde(rename = "versionForUpdatePurposes")]
+				pub version_for_update_purposes: u32,
+			}
+
+			let f = File::open(&preset_path).map_err(|reason| PackError::Unreadable { reason })?;
+
+			serde_json::from_reader::<_, KsVersionOnly>(f).map_err(|reason| PackError::MalformedPreset { reason })
+		}?;
+
+		if ks_version.version_for_update_purposes != self.ksv {
+			return Err(PackError::WrongVersion { wanted: self.ksv, got: ks_version.version_for_update_purposes });
+		}
+
+		let loaded_preset: super::ks_preset::KeysightPresetElement = {
+			let f = File::open(&preset_path).map_err(|reason| PackError::Unreadable { reason })?;
+
+			serde_json::from_reader(f).map_err(|reason| PackError::MalformedPreset { reason })
+		}?;
+
+		debug!("loaded preset");
+
+		debug!("discovering files");
+		let references = references::texture_references(&loaded_preset)
+			.map_err(|reason| PackError::MalformedPreset { reason })?;
+		let found = references
+			.iter()
+			.filter(|reference| reference.enabled || self.policy == CollectPolicy::AllReferenced)
+			.map(|reference| {
+				self.make_found_file(reference.field.texture_type, &reference.name, reference.enabled)
+			});
+
+		// a name resolves to the same file in every field of a directory, so textures used by
+		// several fields are only listed once, and active if any field uses them
+		let mut files: Vec<FoundAsset> = Vec::with_capacity(references.len());
+		for asset in found {
+			let same = |other: &&mut FoundAsset| {
+				other.name == asset.name && other.texture_type.path_name() == asset.texture_type.path_name()
+			};
+			match files.iter_mut().find(same) {
+				Some(other) => other.active |= asset.active,
+				None => files.push(asset),
+			}
+		}
+
+		let (missing, files): (Vec<_>, Vec<_>) =
+			files.into_iter().partition(|asset| asset.action == AssetAction::NotFound);
+		let (builtin, assets): (Vec<_>, Vec<_>) =
+			files.into_iter().partition(|asset| asset.action == AssetAction::Ignore);
+
+		for asset in &missing {
+			warn!(name = %asset.name, typ = ?asset.texture_type, "referenced asset not found");
+		}
+
+		if self.strict && !missing.is_empty() {
+			return Err(PackError::MissingAssets {
+				names: missing.iter().map(|asset| asset.name.clone()).collect(),
+			});
+		}
+
+		Ok(PackablePreset { name: self.preset.clone(), path: preset_path, assets, missing, builtin })
+	}
+
+	#[instrument(skip(self))]
+	fn make_found_file(&self, typ: TextureType, file: &str, active: bool) -> FoundAsset {
+		let dirs = [
+			(self.env.root_asset_dir(), false, AssetAction::Ignore),
+			(self.env.custom_asset_dir(false), false, AssetAction::Pack),
+			(self.env.custom_asset_dir(true), true, AssetAction::Pack),
+		];
+
+		for (dir, random, action) in dirs {
+			let dir = dir.join(typ.path_name());
+			let mut candidates = self
+				.extensions
+				.iter()
+				.map(|ext| (dir.join(format!("{}.{}", file, ext)), ext))
+				.filter(|(path, _)| path.exists());
+
+			if let Some((path, ext)) = candidates.next() {
+				let candidates: Vec<PathBuf> = candidates.map(|(path, _)| path).collect();
+				if !candidates.is_empty() {
+					warn!(path=%path.display(), others=?candidates, "several files for one texture, using the first");
+				}
+
+				debug!(path=%path.display(), ?action, random, "found asset");
+				let warnings = match action {
+					AssetAction::Pack => texture::probe(&path, ext),
+					_ => Vec::new(),
+				};
+				return FoundAsset {
+					name: file.to_owned(),
+					ext: ext.clone(),
+					texture_type: typ,
+					random,
+					active,
+					path,
+					candidates,
+					action,
+					warnings,
+				};
+			}
+		}
+
+		debug!("asset not found");
+		FoundAsset {
+			name:         file.to_owned(),
+			ext:          String::new(),
+			texture_type: typ,
+			random:       false,
+			active,
+			path:         PathBuf::new(),
+			candidates:   Vec::new(),
+			action:       AssetAction::NotFound,
+			warnings:     Vec::new(),
+		}
+	}
+
+	fn check_builtin_preset(&self) -> bool {
+		self.env.root_preset_dir().join(format!("{}.json", self.preset)).exists()
+	}
+}
+
+/// An asset referenced by a preset
+#[derive(Debug)]
+pub struct FoundAsset {
+	pub name:         String,
+	pub ext:          String,
+	pub texture_type: TextureType,
+	pub random:       bool,
+	/// Whether the preset currently uses the asset, see [`CollectPolicy`]
+	pub active:       bool,
+	pub path:         PathBuf,
+	/// Files of the same name with a later extension, which are not packed
+	pub candidates:   Vec<PathBuf>,
+	pub action:       AssetAction,
+	/// Problems seen in the header of the texture, packing reports them again in
+	/// [`PackSummary::warnings`]
+	pub warnings:     Vec<TextureWarning>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AssetAction {
+	NotFound,
+	Ignore,
+	Pack,
+}
+
+/// Metadata supplied by the user when packing
+pub struct ExtraMeta {
+	pub rename:             Option<String>,
+	pub author:             String,
+	pub description:        String,
+	pub version:            u32,
+	pub current_ks_version: u32,
+}
+
+/// A preset with its assets resolved, ready to be packed
+#[derive(Debug)]
+pub struct PackablePreset {
+	name:    String,
+	path:    PathBuf,
+	assets:  Vec<FoundAsset>,
+	missing: Vec<FoundAsset>,
+	builtin: Vec<FoundAsset>,
+}
+
+impl PackablePreset {
+	pub fn name(&self) -> &str { &self.name }
+
+	/// The custom assets that will be packed
+	pub fn assets(&self) -> &[FoundAsset] { &self.assets }
+
+	/// Referenced textures that could not be found, the recipient will see blank textures
+	pub fn missing(&self) -> &[FoundAsset] { &self.missing }
+
+	/// Referenced builtin textures, every installation has them so they are not packed
+	pub fn builtin(&self) -> &[FoundAsset] { &self.builtin }
+
+	/// Writes the preset, its assets and the metadata into a zip archive at `to`
+	pub fn pack(
+		&self,
+		to: impl AsRef<Path>,
+		extra_meta: ExtraMeta,
+		options: &PackOptions,
+	) -> Result<PackSummary, PackError> {
+		let to = to.as_ref();
+		let write = || {
+			let mut writer = PackWriter::create(to, options)?;
+			let name = extra_meta.rename.clone().unwrap_or_else(|| self.name.clone());
+			let meta = writer.write_preset(self, format::PRESET_ENTRY, name, &extra_meta)?;
+			writer.finish(&meta)
+		};
+		write()
+	}
+
+	/// Writes several presets into one bundle at `to`, storing assets they share only once.
+	/// `extra_meta.rename` names the bundle and defaults to the file name of `to`, the presets
+	/// keep their names.
+	pub fn pack_bundle(
+		presets: &[PackablePreset],
+		to: impl AsRef<Path>,
+		extra_meta: ExtraMeta,
+		options: &PackOptions,
+	) -> Result<PackSummary, PackError> {
+		Self::write_bundle(presets, to.as_ref(), extra_meta, options)
+	}
+
+	fn write_bundle(
+		presets: &[PackablePreset],
+		to: &Path,
+		extra_meta: ExtraMeta,
+		options: &PackOptions,
+	) -> Result<PackSummary, PackError> {
+		let mut writer = PackWriter::create(to, options)?;
+		// the assets of all presets are processed together, to keep every worker busy
+		writer.write_assets(presets.iter().flat_map(|preset| &preset.assets))?;
+
+		let mut entries = Vec::with_capacity(presets.len());
+		for (idx, preset) in presets.iter().enumerate() {
+			let file = format::bundle_preset_entry(idx);
+			let metadata = writer.write_preset(preset, &file, preset.name.clone(), &extra_meta)?;
+			entries.push(BundleEntry { file, metadata });
+		}
+
+		let default_name = || to.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+		let requires = format::latest_release(
+			format::BUNDLE_MIN_READER_VERSION,
+			entries.iter().map(|entry| entry.metadata.requires.as_str()),
+		)
+		.to_owned();
+		let meta = BundleMetaData {
+			format_version: format::BUNDLE_FORMAT_VERSION,
+			requires,
+			info:           BundleInfo {
+				name:        extra_meta.rename.unwrap_or_else(default_name),
+				author:      extra_meta.author,
+				description: extra_meta.description,
+				packed:      Utc::now(),
+			},
+			target_version: extra_meta.current_ks_version,
+			presets:        entries,
+		};
+		writer.finish(&meta)
+	}
+}
+
+/// How assets are written when packing
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+	/// Optimise textures before storing them, off by default
+	pub optimise:    Option<Optimise>,
+	pub compression: CompressionProfile,
+	/// Compress with deflate instead of zstd, so zip tools without zstd support can open the
+	/// package
+	pub compatible:  bool,
+	/// Receives the progress of packing and can cancel it
+	pub progress:    Progress,
+}
+
+/// How hard the entries of a package are compressed, trading export time for size
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CompressionProfile {
+	Fast,
+	#[default]
+	Balanced,
+	Max,
+	/// Stores every entry uncompressed
+	StoreOnly,
+}
+
+impl CompressionProfile {
+	pub const ALL: [CompressionProfile; 4] = [
+		CompressionProfile::Fast,
+		CompressionProfile::Balanced,
+		CompressionProfile::Max,
+		CompressionProfile::StoreOnly,
+	];
+
+	/// The options for entries compressed with this profile, using deflate if `compatible`
+	fn file_options(&self, compatible: bool) -> FileOptions {
+		use zip::CompressionMethod::{Deflated, Stored, Zstd};
+
+		let (method, level) = match (self, compatible) {
+			(CompressionProfile::StoreOnly, _) => (Stored, None),
+			(CompressionProfile::Fast, false) => (Zstd, Some(1)),
+			(CompressionProfile::Balanced, false) => (Zstd, Some(9)),
+			(CompressionProfile::Max, false) => (Zstd, Some(19)),
+			(CompressionProfile::Fast, true) => (Deflated, Some(1)),
+			(CompressionProfile::Balanced, true) => (Deflated, Some(6)),
+			(CompressionProfile::Max, true) => (Deflated, Some(9)),
+		};
+		FileOptions::default().compression_method(method).compression_level(level).large_file(false)
+	}
+}
+
+impl fmt::Display for CompressionProfile {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			CompressionProfile::Fast => "Fast",
+			CompressionProfile::Balanced => "Balanced",
+			CompressionProfile::Max => "Maximum",
+			CompressionProfile::StoreOnly => "Store only",
+		})
+	}
+}
+
+/// Bytes from the start of an entry used to estimate how well it compresses
+const COMPRESSIBILITY_SAMPLE: usize = 64 * 1024;
+
+/// Whether compressing `data` is worth it, estimated by quickly compressing its start. Most
+/// texture formats are compressed already and would only cost time to compress again.
+fn compresses(data: &[u8]) -> bool {
+	/// Entries are stored unless compressing saves at least this share of the sample
+	const MIN_SAVING: f64 = 0.05;
+
+	let sample = &data[..data.len().min(COMPRESSIBILITY_SAMPLE)];
+	match zstd::bulk::compress(sample, 1) {
+		Ok(compressed) => (compressed.len() as f64) < sample.len() as f64 * (1.0 - MIN_SAVING),
+		Err(_) => true,
+	}
+}
+
+/// Sizes of the distinct assets written into a package, before compression
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PackSummary {
+	pub assets:        usize,
+	/// Distinct assets that optimising re-encoded or scaled down
+	pub optimised:     usize,
+	/// Size of the textures as they were found
+	pub original_size: u64,
+	/// Size of the textures as they were stored, after optimisation
+	pub packed_size:   u64,
+	/// Problems with the textures as they were stored, which did not stop packing them
+	pub warnings:      Vec<PackWarning>,
+}
+
+/// A texture that was packed despite a problem
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PackWarning {
+	pub path:    PathBuf,
+	pub warning: TextureWarning,
+}
+
+impl PackSummary {
+	/// Bytes saved by optimising textures
+	pub fn saved(&self) -> u64 { self.original_size.saturating_sub(self.packed_size) }
+}
+
+/// A distinct texture written into the archive
+#[derive(Clone, Copy)]
+struct Stored {
+	hash:      blake3::Hash,
+	optimised: bool,
+	image:     ImageInfo,
+}
+
+/// A texture validated, optimised and compressed on a worker thread, waiting to be written
+struct Prepared {
+	path:        PathBuf,
+	/// File name of the texture, for progress reports
+	name:        String,
+	/// Hash and format of the original texture
+	source:      blake3::Hash,
+	format:      ImageFormat,
+	stored:      Stored,
+	source_size: u64,
+	stored_size: u64,
+	warnings:    Vec<PackWarning>,
+	/// An archive holding only the compressed asset entry, named [`PREPARED_ENTRY`] as its hash
+	/// is only known once it is written
+	entry:       SpooledTempFile,
+}
+
+/// Compressed entries larger than this are moved from memory to a temporary file while they
+/// wait to be written
+const SPOOL_SIZE: usize = 1024 * 1024;
+
+/// Name of the entry in the archive of a [`Prepared`] texture, renamed to its hash when copied
+/// into the package
+const PREPARED_ENTRY: &str = "asset";
+
+fn file_name(asset: &FoundAsset) -> String { format!("{}.{}", asset.name, asset.ext) }
+
+/// Validates and optionally optimises a texture, and compresses it into an archive of its own,
+/// which stays in memory only if it is smaller than [`SPOOL_SIZE`]. The file is read once to
+/// validate it and once to hash and compress it, or only once if it is optimised.
+fn prepare(asset: &FoundAsset, options: &PackOptions, zipoptions: FileOptions) -> Result<Prepared, PackError> {
+	if options.progress.is_cancelled() {
+		return Err(PackError::Cancelled);
+	}
+
+	let invalid = |reason| PackError::InvalidTexture { path: asset.path.clone(), reason };
+	let io_error = |reason| PackError::PackIoError { reason };
+	let zip_error = |reason| PackError::ZipError { reason };
+	let mut reader = BufReader::new(File::open(&asset.path).map_err(io_error)?);
+	let format = ImageFormat::detect(reader.fill_buf().map_err(io_error)?, &asset.ext);
+	let mut entry = ZipWriter::new(SpooledTempFile::new(SPOOL_SIZE));
+
+	// optimising needs the whole texture in memory, otherwise it is validated and copied from
+	// the file so memory use does not depend on the texture size
+	let (source, source_size, stored, stored_size) = match &options.optimise {
+		Some(optimise) => {
+			let mut data = Vec::new();
+			reader.read_to_end(&mut data).map_err(io_error)?;
+			let hash = blake3::hash(&data);
+			let source_size = data.len() as u64;
+			let decoded = texture::decode(&data, format).map_err(invalid)?;
+
+			let (stored, stored_data) = match texture::optimise(&data, format, &decoded, optimise).map_err(invalid)? {
+				Some(optimised) => {
+					let stored =
+						Stored { hash: blake3::hash(&optimised.data), optimised: true, image: optimised.image };
+					debug!(%hash, optimised=%stored.hash, from=data.len(), to=optimised.data.len(), "optimised texture");
+					(stored, optimised.data)
+				},
+				None => (Stored { hash, optimised: false, image: ImageInfo::of(&decoded) }, data),
+			};
+
+			start_entry(&mut entry, &stored_data, options, zipoptions).map_err(zip_error)?;
+			entry.write_all(&stored_data).map_err(io_error)?;
+			(hash, source_size, stored, stored_data.len() as u64)
+		},
+		None => {
+			let image = ImageInfo::read(&mut reader, format).map_err(invalid)?;
+			reader.rewind().map_err(io_error)?;
+			let (hash, size) = copy_hashed(reader, &mut entry, options, zipoptions)?;
+			(hash, size, Stored { hash, optimised: false, image }, size)
+		},
+	};
+
+	let mut warnings = Vec::new();
+	let format_warning = TextureWarning::of_format(format, &asset.ext);
+	for warning in format_warning.into_iter().chain(TextureWarning::of_image(stored.image)) {
+		warn!(path=%asset.path.display(), %warning, "packing texture with a warning");
+		warnings.push(PackWarning { path: asset.path.clone(), warning });
+	}
+
+	Ok(Prepared {
+		path: asset.path.clone(),
+		name: file_name(asset),
+		source,
+		format,
+		stored,
+		source_size,
+		stored_size,
+		warnings,
+		entry: entry.finish().map_err(zip_error)?,
+	})
+}
+
+/// Starts the entry of a prepared texture, which is stored if `sample` does not compress
+fn start_entry(
+	entry: &mut ZipWriter<SpooledTempFile>,
+	sample: &[u8],
+	options: &PackOptions,
+	zipoptions: FileOptions,
+) -> ZipResult<()> {
+	let zipoptions = if compresses(sample) {
+		zipoptions
+	} else {
+		debug!("storing incompressible asset");
+		CompressionProfile::StoreOnly.file_options(options.compatible)
+	};
+	entry.start_file(PREPARED_ENTRY, zipoptions)
+}
+
+/// Copies a texture into the entry of a prepared texture, hashing it on the way. Returns the
+/// hash and size of the texture.
+fn copy_hashed(
+	mut reader: impl Read,
+	entry: &mut ZipWriter<SpooledTempFile>,
+	options: &PackOptions,
+	zipoptions: FileOptions,
+) -> Result<(blake3::Hash, u64), PackError> {
+	let io_error = |reason| PackError::PackIoError { reason };
+
+	let mut head = Vec::with_capacity(COMPRESSIBILITY_SAMPLE);
+	(&mut reader).take(COMPRESSIBILITY_SAMPLE as u64).read_to_end(&mut head).map_err(io_error)?;
+	start_entry(entry, &head, options, zipoptions).map_err(|reason| PackError::ZipError { reason })?;
+
+	let mut hasher = blake3::Hasher::new();
+	hasher.update(&head);
+	entry.write_all(&head).map_err(io_error)?;
+	let mut size = head.len() as u64;
+
+	let mut read_buffer = [0u8; 1024 * 64];
+	loop {
+		if options.progress.is_cancelled() {
+			return Err(PackError::Cancelled);
+		}
+
+		let read = reader.read(&mut read_buffer).map_err(io_error)?;
+		if read == 0 {
+			break;
+		}
+
+		hasher.update(&read_buffer[..read]);
+		entry.write_all(&read_buffer[..read]).map_err(io_error)?;
+		size += read as u64;
+	}
+
+	Ok((hasher.finalize(), size))
+}
+
+/// Writes presets and their assets into an archive, storing every distinct asset once. The
+/// archive is a temporary file that replaces the package only once it is finished, so a failed
+/// or cancelled pack leaves an existing package as it was.
+struct PackWriter {
+	zipfile:        ZipWriter<NamedTempFile>,
+	/// Where the finished package goes
+	to:             PathBuf,
+	/// Options of compressed entries
+	zipoptions:     FileOptions,
+	options:        PackOptions,
+	/// The hash and format of every texture file processed
+	files:          BTreeMap<PathBuf, (blake3::Hash, ImageFormat)>,
+	/// The stored form of the textures already processed, by the hash of their original
+	sources:        BTreeMap<[u8; blake3::OUT_LEN], Stored>,
+	/// Hashes of the asset entries in the archive, textures can optimise to the same contents
+	hashes_written: BTreeSet<[u8; blake3::OUT_LEN]>,
+	summary:        PackSummary,
+	/// Counts the bytes of the textures and presets written
+	tracker:        Tracker,
+}
+
+impl PackWriter {
+	fn create(to: &Path, options: &PackOptions) -> Result<Self, PackError> {
+		let output = temp_package(to).map_err(|reason| PackError::PackIoError { reason })?;
+		let mut zipfile = ZipWriter::new(output);
+
+		let zipoptions = options.compression.file_options(options.compatible);
+
+		zipfile
+			.add_directory("assets", zipoptions)
+			.map_err(|reason| PackError::ZipError { reason })?;
+
+		Ok(Self {
+			zipfile,
+			to: to.to_owned(),
+			zipoptions,
+			options: options.clone(),
+			files: BTreeMap::new(),
+			sources: BTreeMap::new(),
+			hashes_written: BTreeSet::new(),
+			summary: PackSummary::default(),
+			tracker: Tracker::new(&options.progress, 0),
+		})
+	}
+
+	/// Validates, optimises and compresses the texture files not processed yet on the worker
+	/// pool. The compressed entries are written in the order of `assets`, so packages do not
+	/// depend on scheduling.
+	fn write_assets<'a>(&mut self, assets: impl IntoIterator<Item = &'a FoundAsset>) -> Result<(), PackError> {
+		let mut queued = BTreeSet::new();
+		let new: Vec<&FoundAsset> = assets
+			.into_iter()
+			.filter(|asset| !self.files.contains_key(&asset.path) && queued.insert(&asset.path))
+			.collect();
+		self.tracker.add_total(new.iter().filter_map(|asset| asset.path.metadata().ok()).map(|m| m.len()).sum());
+
+		// compressed entries wait in temporary files until they are written, so only a few are
+		// prepared at a time
+		let options = self.options.clone();
+		for batch in new.chunks(rayon::current_num_threads() * 2) {
+			let prepared = batch
+				.par_iter()
+				.map(|asset| prepare(asset, &options, self.zipoptions))
+				.collect::<Result<Vec<_>, _>>()?;
+			for prepared in prepared {
+				self.write_prepared(prepared)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Copies a compressed entry into the archive under its hash. Identical contents are only
+	/// stored once, but every asset needs an entry to be restored under its name.
+	fn write_prepared(&mut self, prepared: Prepared) -> Result<(), PackError> {
+		let zip_error = |reason| PackError::ZipError { reason };
+
+		self.files.insert(prepared.path, (prepared.source, prepared.format));
+		self.summary.warnings.extend(prepared.warnings);
+		self.tracker.advance(&prepared.name, prepared.source_size);
+		if self.sources.contains_key(prepared.source.as_bytes()) {
+			info!(hash=%prepared.source, "already wrote this hash");
+			return Ok(());
+		}
+
+		self.sources.insert(prepared.source.into(), prepared.stored);
+		self.summary.assets += 1;
+		self.summary.optimised += usize::from(prepared.stored.optimised);
+		self.summary.original_size += prepared.source_size;
+		self.summary.packed_size += prepared.stored_size;
+
+		if self.hashes_written.insert(prepared.stored.hash.into()) {
+			let mut entry = ZipArchive::new(prepared.entry).map_err(zip_error)?;
+			let file = entry.by_index(0).map_err(zip_error)?;
+			let name = format!("assets/{}", prepared.stored.hash.to_hex());
+			self.zipfile.raw_copy_file_rename(file, name).map_err(zip_error)?;
+		}
+
+		Ok(())
+	}
+
+	/// Writes the assets of `preset` that are not yet in the archive, and the preset itself as
+	/// `entry`
+	fn write_preset(
+		&mut self,
+		preset: &PackablePreset,
+		entry: &str,
+		name: String,
+		extra_meta: &ExtraMeta,
+	) -> Result<PackMetaData, PackError> {
+		self.write_assets(&preset.assets)?;
+
+		let mut asset_entries = Vec::with_capacity(preset.assets.len());
+		for asset in &preset.assets {
+			let (hash, format) = self.files[&asset.path];
+			let stored = self.sources[hash.as_bytes()];
+
+			let mut meta_entry = MetaEntry::new(
+				stored.hash.to_hex().as_str(),
+				&asset.name,
+				&asset.ext,
+				asset.texture_type,
+				asset.random,
+			);
+			meta_entry.format = format;
+			meta_entry.image = Some(stored.image);
+			meta_entry.original_hash = stored.optimised.then(|| format!("{}", hash.to_hex()));
+			asset_entries.push(meta_entry);
+		}
+
+		let mut preset_file =
+			File::open(&preset.path).map_err(|reason| PackError::PackIoError { reason })?;
+		self.zipfile
+			.start_file(entry, self.zipoptions)
+			.map_err(|reason| PackError::ZipError { reason })?;
+		let written = std::io::copy(&mut preset_file, &mut self.zipfile)
+			.map_err(|reason| PackError::PackIoError { reason })?;
+		self.tracker.add_total(written);
+		self.tracker.advance(&format!("{}.json", preset.name), written);
+
+		Ok(PackMetaData::new(
+			name,
+			&extra_meta.author,
+			&extra_meta.description,
+			extra_meta.version,
+			extra_meta.current_ks_version,
+			asset_entries,
+		))
+	}
+
+	/// Writes the metadata and completes the archive
+	fn finish(mut self, meta: &impl serde::Serialize) -> Result<PackSummary, PackError> {
+		self.zipfile
+			.start_file(format::METADATA_ENTRY, self.zipoptions)
+			.map_err(|reason| PackError::ZipError { reason })?;
+		serde_json::to_writer(&mut self.zipfile, meta)
+			.map_err(|reason| PackError::MalformedMeta { reason })?;
+
+		let output = self.zipfile.finish().map_err(|reason| PackError::ZipError { reason })?;
+		output.persist(&self.to).map_err(|why| PackError::PackIoError { reason: why.error })?;
+		Ok(self.summary)
+	}
+}
+
+/// Creates the temporary file of a package in the directory of `to`, so it can be renamed to
+/// `to`. It gets the permissions of a file created normally, not only the owner's.
+fn temp_package(to: &Path) -> io::Result<NamedTempFile> {
+	let dir = match to.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+
+	let mut builder = tempfile::Builder::new();
+	builder.prefix(".kspacker");
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		builder.permissions(std::fs::Permissions::from_mode(0o666));
+	}
+	builder.tempfile_in(dir)
+}
@@ -0,0 +1,102 @@
+//! Progress reporting and cancellation of packing and unpacking
+//!
+//! A [`Progress`] is passed to packing in [`crate::PackOptions`], or to
+//! [`crate::PackedFile::unpack_with`]. It can be shared with another thread, which receives
+//! updates through a callback or a channel and may cancel the operation at any time.
+use std::{
+	fmt,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc,
+		Arc,
+		Mutex,
+	},
+};
+
+/// The state of a running operation
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgressUpdate {
+	/// Bytes processed so far
+	pub done:    u64,
+	/// Bytes the whole operation processes, an estimate that `done` may briefly exceed
+	pub total:   u64,
+	/// File name of the asset or preset processed last
+	pub current: String,
+}
+
+impl ProgressUpdate {
+	/// Completed share between 0 and 1
+	pub fn fraction(&self) -> f32 {
+		if self.total == 0 {
+			0.0
+		} else {
+			(self.done as f64 / self.total as f64).min(1.0) as f32
+		}
+	}
+}
+
+type Callback = dyn Fn(&ProgressUpdate) + Send + Sync;
+
+/// Receives progress updates and carries the cancellation flag, clones share both
+#[derive(Clone, Default)]
+pub struct Progress {
+	callback:  Option<Arc<Callback>>,
+	cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+	/// Calls `callback` with every update, from whichever thread makes progress
+	pub fn new(callback: impl Fn(&ProgressUpdate) + Send + Sync + 'static) -> Self {
+		Self { callback: Some(Arc::new(callback)), cancelled: Arc::default() }
+	}
+
+	/// Sends every update to the returned receiver
+	pub fn channel() -> (Self, mpsc::Receiver<ProgressUpdate>) {
+		let (sender, receiver) = mpsc::channel();
+		let sender = Mutex::new(sender);
+		// the receiver may be gone once the caller lost interest, which is not an error
+		let progress = Self::new(move |update| drop(sender.lock().unwrap().send(update.clone())));
+		(progress, receiver)
+	}
+
+	/// Asks the operation to stop, it then fails with a `Cancelled` error
+	pub fn cancel(&self) { self.cancelled.store(true, Ordering::Relaxed) }
+
+	pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::Relaxed) }
+
+	fn report(&self, update: &ProgressUpdate) {
+		if let Some(callback) = &self.callback {
+			callback(update);
+		}
+	}
+}
+
+impl fmt::Debug for Progress {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Progress")
+			.field("callback", &self.callback.is_some())
+			.field("cancelled", &self.is_cancelled())
+			.finish()
+	}
+}
+
+/// Counts the bytes of one operation and reports them
+pub(crate) struct Tracker {
+	progress: Progress,
+	done:     u64,
+	total:    u64,
+}
+
+impl Tracker {
+	pub fn new(progress: &Progress, total: u64) -> Self { Self { progress: progress.clone(), done: 0, total } }
+
+	pub fn is_cancelled(&self) -> bool { self.progress.is_cancelled() }
+
+	pub fn add_total(&mut self, bytes: u64) { self.total += bytes }
+
+	/// Records that `bytes` of `current` were processed
+	pub fn advance(&mut self, current: &str, bytes: u64) {
+		self.done += bytes;
+		self.progress.report(&ProgressUpdate { done: self.done, total: self.total, current: current.to_owned() });
+	}
+}
@@ -0,0 +1,338 @@
+//! Texture references inside the json of a preset
+//!
+//! Every preset field that can name a texture is listed in [`TEXTURE_FIELDS`], which drives
+//! both collecting the assets of a preset and renaming them on import. The layers in the
+//! `lightArray` of light bars are not modelled, so their materials are found by key instead:
+//! any object inside a layer with a material texture key, like `diffuseTexture`, is treated as
+//! a material.
+use serde_json::{Map, Value};
+
+use super::{KeysightPresetElement, TextureType};
+
+/// A preset field naming a texture
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TextureField {
+	/// Json keys from the root of the preset separated by `.`, a key ending in `[]` selects
+	/// every element of the array
+	pub path:         &'static str,
+	pub texture_type: TextureType,
+	/// Boolean fields in the form of `path` that all have to be set for the texture to be used.
+	/// Their arrays refer to the same elements as the arrays of `path`.
+	pub enabled_by:   &'static [&'static str],
+}
+
+const fn field(
+	path: &'static str,
+	texture_type: TextureType,
+	enabled_by: &'static [&'static str],
+) -> TextureField {
+	TextureField { path, texture_type, enabled_by }
+}
+
+/// All preset fields that name a texture
+pub const TEXTURE_FIELDS: &[TextureField] = &[
+	field("effects.keypresses.keypressMaterial.diffuseTexture", TextureType::Diffuse, &["effects.keypresses.keypressMaterial.diffuseUseTexture"]),
+	field("effects.keypresses.keypressMaterial.emissiveTexture", TextureType::Emissive, &["effects.keypresses.keypressMaterial.emissiveUseTexture"]),
+	field("effects.keypresses.keypressMaterial.maskTexture", TextureType::Mask, &["effects.keypresses.keypressMaterial.maskOn"]),
+	field("effects.keypresses.keypressMaterial.metalnessTexture", TextureType::Metalness, &["effects.keypresses.keypressMaterial.metalnessUseTexture"]),
+	field("effects.keypresses.keypressMaterial.normalTexture", TextureType::Normal, &["effects.keypresses.keypressMaterial.normalOn"]),
+	field("effects.keypresses.keypressMaterial.roughnessTexture", TextureType::Roughness, &["effects.keypresses.keypressMaterial.roughnessUseTexture"]),
+	field("effects.keypresses.keypressMaterial.specularTexture", TextureType::Specular, &["effects.keypresses.keypressMaterial.specularUseTexture"]),
+	field("effects.keypresses.keypressMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("effects.noteObjects.noteObjectMaterial.diffuseTexture", TextureType::Diffuse, &["effects.noteObjects.noteObjectMaterial.diffuseUseTexture"]),
+	field("effects.noteObjects.noteObjectMaterial.emissiveTexture", TextureType::Emissive, &["effects.noteObjects.noteObjectMaterial.emissiveUseTexture"]),
+	field("effects.noteObjects.noteObjectMaterial.maskTexture", TextureType::Mask, &["effects.noteObjects.noteObjectMaterial.maskOn"]),
+	field("effects.noteObjects.noteObjectMaterial.metalnessTexture", TextureType::Metalness, &["effects.noteObjects.noteObjectMaterial.metalnessUseTexture"]),
+	field("effects.noteObjects.noteObjectMaterial.normalTexture", TextureType::Normal, &["effects.noteObjects.noteObjectMaterial.normalOn"]),
+	field("effects.noteObjects.noteObjectMaterial.roughnessTexture", TextureType::Roughness, &["effects.noteObjects.noteObjectMaterial.roughnessUseTexture"]),
+	field("effects.noteObjects.noteObjectMaterial.specularTexture", TextureType::Specular, &["effects.noteObjects.noteObjectMaterial.specularUseTexture"]),
+	field("effects.noteObjects.noteObjectMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("effects.noteObjects.noteBorderMaterial.diffuseTexture", TextureType::Diffuse, &["effects.noteObjects.noteBorderMaterial.diffuseUseTexture"]),
+	field("effects.noteObjects.noteBorderMaterial.emissiveTexture", TextureType::Emissive, &["effects.noteObjects.noteBorderMaterial.emissiveUseTexture"]),
+	field("effects.noteObjects.noteBorderMaterial.maskTexture", TextureType::Mask, &["effects.noteObjects.noteBorderMaterial.maskOn"]),
+	field("effects.noteObjects.noteBorderMaterial.metalnessTexture", TextureType::Metalness, &["effects.noteObjects.noteBorderMaterial.metalnessUseTexture"]),
+	field("effects.noteObjects.noteBorderMaterial.normalTexture", TextureType::Normal, &["effects.noteObjects.noteBorderMaterial.normalOn"]),
+	field("effects.noteObjects.noteBorderMaterial.roughnessTexture", TextureType::Roughness, &["effects.noteObjects.noteBorderMaterial.roughnessUseTexture"]),
+	field("effects.noteObjects.noteBorderMaterial.specularTexture", TextureType::Specular, &["effects.noteObjects.noteBorderMaterial.specularUseTexture"]),
+	field("effects.noteObjects.noteBorderMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.backdropMaterial.diffuseTexture", TextureType::Diffuse, &["scene.backdropMaterial.diffuseUseTexture"]),
+	field("scene.backdropMaterial.emissiveTexture", TextureType::Emissive, &["scene.backdropMaterial.emissiveUseTexture"]),
+	field("scene.backdropMaterial.maskTexture", TextureType::Mask, &["scene.backdropMaterial.maskOn"]),
+	field("scene.backdropMaterial.metalnessTexture", TextureType::Metalness, &["scene.backdropMaterial.metalnessUseTexture"]),
+	field("scene.backdropMaterial.normalTexture", TextureType::Normal, &["scene.backdropMaterial.normalOn"]),
+	field("scene.backdropMaterial.roughnessTexture", TextureType::Roughness, &["scene.backdropMaterial.roughnessUseTexture"]),
+	field("scene.backdropMaterial.specularTexture", TextureType::Specular, &["scene.backdropMaterial.specularUseTexture"]),
+	field("scene.backdropMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.damperMaterial.diffuseTexture", TextureType::Diffuse, &["scene.damperMaterial.diffuseUseTexture"]),
+	field("scene.damperMaterial.emissiveTexture", TextureType::Emissive, &["scene.damperMaterial.emissiveUseTexture"]),
+	field("scene.damperMaterial.maskTexture", TextureType::Mask, &["scene.damperMaterial.maskOn"]),
+	field("scene.damperMaterial.metalnessTexture", TextureType::Metalness, &["scene.damperMaterial.metalnessUseTexture"]),
+	field("scene.damperMaterial.normalTexture", TextureType::Normal, &["scene.damperMaterial.normalOn"]),
+	field("scene.damperMaterial.roughnessTexture", TextureType::Roughness, &["scene.damperMaterial.roughnessUseTexture"]),
+	field("scene.damperMaterial.specularTexture", TextureType::Specular, &["scene.damperMaterial.specularUseTexture"]),
+	field("scene.damperMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.octaveMaterial.diffuseTexture", TextureType::Diffuse, &["scene.octaveMaterial.diffuseUseTexture"]),
+	field("scene.octaveMaterial.emissiveTexture", TextureType::Emissive, &["scene.octaveMaterial.emissiveUseTexture"]),
+	field("scene.octaveMaterial.maskTexture", TextureType::Mask, &["scene.octaveMaterial.maskOn"]),
+	field("scene.octaveMaterial.metalnessTexture", TextureType::Metalness, &["scene.octaveMaterial.metalnessUseTexture"]),
+	field("scene.octaveMaterial.normalTexture", TextureType::Normal, &["scene.octaveMaterial.normalOn"]),
+	field("scene.octaveMaterial.roughnessTexture", TextureType::Roughness, &["scene.octaveMaterial.roughnessUseTexture"]),
+	field("scene.octaveMaterial.specularTexture", TextureType::Specular, &["scene.octaveMaterial.specularUseTexture"]),
+	field("scene.octaveMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.overlayMaterial.diffuseTexture", TextureType::Diffuse, &["scene.overlayMaterial.diffuseUseTexture"]),
+	field("scene.overlayMaterial.emissiveTexture", TextureType::Emissive, &["scene.overlayMaterial.emissiveUseTexture"]),
+	field("scene.overlayMaterial.maskTexture", TextureType::Mask, &["scene.overlayMaterial.maskOn"]),
+	field("scene.overlayMaterial.metalnessTexture", TextureType::Metalness, &["scene.overlayMaterial.metalnessUseTexture"]),
+	field("scene.overlayMaterial.normalTexture", TextureType::Normal, &["scene.overlayMaterial.normalOn"]),
+	field("scene.overlayMaterial.roughnessTexture", TextureType::Roughness, &["scene.overlayMaterial.roughnessUseTexture"]),
+	field("scene.overlayMaterial.specularTexture", TextureType::Specular, &["scene.overlayMaterial.specularUseTexture"]),
+	field("scene.overlayMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.pianoBlackKeyMaterial.diffuseTexture", TextureType::Diffuse, &["scene.pianoBlackKeyMaterial.diffuseUseTexture"]),
+	field("scene.pianoBlackKeyMaterial.emissiveTexture", TextureType::Emissive, &["scene.pianoBlackKeyMaterial.emissiveUseTexture"]),
+	field("scene.pianoBlackKeyMaterial.maskTexture", TextureType::Mask, &["scene.pianoBlackKeyMaterial.maskOn"]),
+	field("scene.pianoBlackKeyMaterial.metalnessTexture", TextureType::Metalness, &["scene.pianoBlackKeyMaterial.metalnessUseTexture"]),
+	field("scene.pianoBlackKeyMaterial.normalTexture", TextureType::Normal, &["scene.pianoBlackKeyMaterial.normalOn"]),
+	field("scene.pianoBlackKeyMaterial.roughnessTexture", TextureType::Roughness, &["scene.pianoBlackKeyMaterial.roughnessUseTexture"]),
+	field("scene.pianoBlackKeyMaterial.specularTexture", TextureType::Specular, &["scene.pianoBlackKeyMaterial.specularUseTexture"]),
+	field("scene.pianoBlackKeyMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("scene.pianoWhiteKeyMaterial.diffuseTexture", TextureType::Diffuse, &["scene.pianoWhiteKeyMaterial.diffuseUseTexture"]),
+	field("scene.pianoWhiteKeyMaterial.emissiveTexture", TextureType::Emissive, &["scene.pianoWhiteKeyMaterial.emissiveUseTexture"]),
+	field("scene.pianoWhiteKeyMaterial.maskTexture", TextureType::Mask, &["scene.pianoWhiteKeyMaterial.maskOn"]),
+	field("scene.pianoWhiteKeyMaterial.metalnessTexture", TextureType::Metalness, &["scene.pianoWhiteKeyMaterial.metalnessUseTexture"]),
+	field("scene.pianoWhiteKeyMaterial.normalTexture", TextureType::Normal, &["scene.pianoWhiteKeyMaterial.normalOn"]),
+	field("scene.pianoWhiteKeyMaterial.roughnessTexture", TextureType::Roughness, &["scene.pianoWhiteKeyMaterial.roughnessUseTexture"]),
+	field("scene.pianoWhiteKeyMaterial.specularTexture", TextureType::Specular, &["scene.pianoWhiteKeyMaterial.specularUseTexture"]),
+	field("scene.pianoWhiteKeyMaterial.textureDisplace", TextureType::Displacement, &[]),
+
+	field("effects.noteObjects.noteObjectShape", TextureType::NoteShape, &["effects.noteObjects.noteObjectsEnabled"]),
+	field("effects.noteObjects.noteShapeOverride", TextureType::NoteShape, &["effects.noteObjects.noteObjectsEnabled"]),
+	field("effects.noteObjects.noteObjectBorder", TextureType::NoteBorder, &["effects.noteObjects.noteObjectsEnabled"]),
+	field("effects.particles.particleV2Array[].shape", TextureType::Shape, &[
+		"effects.particles.particlesEnabled",
+		"effects.particles.particleV2Array[].enabled",
+	]),
+	field("effects.pulses.pulseArrayV2[].stencil", TextureType::Stencil, &[
+		"effects.pulses.pulsesEnabled",
+		"effects.pulses.pulseArrayV2[].enabled",
+	]),
+	field("effects.pulses.pulseArrayV2[].worldStencil", TextureType::WorldStencil, &[
+		"effects.pulses.pulsesEnabled",
+		"effects.pulses.pulseArrayV2[].enabled",
+		"effects.pulses.pulseArrayV2[].worldStencilOn",
+	]),
+];
+
+/// The unmodelled layers of every light bar, searched for materials
+const LIGHT_LAYERS: &str = "widgets.lightBars[].lightArray[]";
+
+/// Flags that all have to be set for the textures of a light bar layer to be used, in the form
+/// of [`TextureField::path`]
+const LIGHT_LAYER_ENABLED_BY: &[&str] = &["widgets.lightBarsEnabled", "widgets.lightBars[].enabled"];
+
+/// The last key of a path
+fn last_key(path: &str) -> &str { path.rsplit('.').next().unwrap_or(path) }
+
+/// The first material field of the table with the key `key`, which every material shares
+fn material_field(key: &str) -> Option<&'static TextureField> {
+	TEXTURE_FIELDS.iter().find(|field| field.path.contains("Material.") && last_key(field.path) == key)
+}
+
+/// A texture named by a preset
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextureReference {
+	pub field:   &'static TextureField,
+	pub name:    String,
+	/// Whether the preset uses the texture, see [`TextureField::enabled_by`]
+	pub enabled: bool,
+}
+
+/// Lists every texture the preset names in any of the [`TEXTURE_FIELDS`] in table order, followed
+/// by the textures of light bar layers
+pub fn texture_references(
+	preset: &KeysightPresetElement,
+) -> Result<Vec<TextureReference>, serde_json::Error> {
+	let json = serde_json::to_value(preset)?;
+	let mut references = Vec::new();
+
+	for field in TEXTURE_FIELDS {
+		visit(&json, field.path, &mut Vec::new(), &mut |value, indices| {
+			let name = match value.as_str() {
+				Some(name) if !name.is_empty() => name,
+				_ => return,
+			};
+
+			let enabled = field
+				.enabled_by
+				.iter()
+				.all(|flag| lookup(&json, flag, indices).and_then(Value::as_bool).unwrap_or(false));
+			references.push(TextureReference { field, name: name.to_owned(), enabled });
+		});
+	}
+
+	visit(&json, LIGHT_LAYERS, &mut Vec::new(), &mut |layer, indices| {
+		let layer_enabled = LIGHT_LAYER_ENABLED_BY
+			.iter()
+			.all(|flag| lookup(&json, flag, indices).and_then(Value::as_bool).unwrap_or(false));
+
+		visit_materials(layer, &mut |field, value, material| {
+			let name = match value.as_str() {
+				Some(name) if !name.is_empty() => name,
+				_ => return,
+			};
+
+			// the flags of a material are its own keys
+			let enabled = layer_enabled
+				&& field
+					.enabled_by
+					.iter()
+					.all(|flag| material.get(last_key(flag)).and_then(Value::as_bool).unwrap_or(false));
+			references.push(TextureReference { field, name: name.to_owned(), enabled });
+		});
+	});
+
+	Ok(references)
+}
+
+/// Points every reference to the texture `from` at `to` instead, returning how many references
+/// were changed.
+///
+/// Textures are looked up by directory, so references of every type sharing the directory of
+/// `typ` are renamed.
+pub fn rename_texture(preset: &mut Value, typ: TextureType, from: &str, to: &str) -> usize {
+	let mut renamed = 0;
+	for field in TEXTURE_FIELDS.iter().filter(|f| f.texture_type.path_name() == typ.path_name()) {
+		visit_mut(preset, field.path, &mut |value| {
+			if value.as_str() == Some(from) {
+				*value = Value::String(to.to_owned());
+				renamed += 1;
+			}
+		});
+	}
+
+	visit_mut(preset, LIGHT_LAYERS, &mut |layer| {
+		visit_materials_mut(layer, &mut |field, value| {
+			if field.texture_type.path_name() == typ.path_name() && value.as_str() == Some(from) {
+				*value = Value::String(to.to_owned());
+				renamed += 1;
+			}
+		});
+	});
+	renamed
+}
+
+/// Calls `f` with every value of a material texture key anywhere inside `value`, its field and
+/// the object it is in
+fn visit_materials<'a>(
+	value: &'a Value,
+	f: &mut dyn FnMut(&'static TextureField, &'a Value, &'a Map<String, Value>),
+) {
+	match value {
+		Value::Object(object) => {
+			for (key, value) in object {
+				match material_field(key) {
+					Some(field) if value.is_string() => f(field, value, object),
+					_ => visit_materials(value, f),
+				}
+			}
+		},
+		Value::Array(values) => values.iter().for_each(|value| visit_materials(value, f)),
+		_ => {},
+	}
+}
+
+fn visit_materials_mut(value: &mut Value, f: &mut dyn FnMut(&'static TextureField, &mut Value)) {
+	match value {
+		Value::Object(object) => {
+			for (key, value) in object {
+				match material_field(key) {
+					Some(field) if value.is_string() => f(field, value),
+					_ => visit_materials_mut(value, f),
+				}
+			}
+		},
+		Value::Array(values) => values.iter_mut().for_each(|value| visit_materials_mut(value, f)),
+		_ => {},
+	}
+}
+
+/// Splits the first key off `path`, returning whether it selects the elements of an array
+fn split_key(path: &str) -> (&str, bool, Option<&str>) {
+	let (segment, rest) = path.split_once('.').map_or((path, None), |(s, r)| (s, Some(r)));
+	match segment.strip_suffix("[]") {
+		Some(key) => (key, true, rest),
+		None => (segment, false, rest),
+	}
+}
+
+/// Calls `f` with every value at `path`, and the indices of the array elements it is in
+fn visit<'a>(
+	value: &'a Value,
+	path: &str,
+	indices: &mut Vec<usize>,
+	f: &mut dyn FnMut(&'a Value, &[usize]),
+) {
+	let (key, is_array, rest) = split_key(path);
+	let value = match value.get(key) {
+		Some(value) => value,
+		None => return,
+	};
+
+	let mut descend = |value: &'a Value, indices: &mut Vec<usize>| match rest {
+		Some(rest) => visit(value, rest, indices, f),
+		None => f(value, indices),
+	};
+
+	if is_array {
+		for (idx, element) in value.as_array().into_iter().flatten().enumerate() {
+			indices.push(idx);
+			descend(element, indices);
+			indices.pop();
+		}
+	} else {
+		descend(value, indices);
+	}
+}
+
+fn visit_mut(value: &mut Value, path: &str, f: &mut dyn FnMut(&mut Value)) {
+	let (key, is_array, rest) = split_key(path);
+	let value = match value.get_mut(key) {
+		Some(value) => value,
+		None => return,
+	};
+
+	let mut descend = |value: &mut Value| match rest {
+		Some(rest) => visit_mut(value, rest, f),
+		None => f(value),
+	};
+
+	if is_array {
+		value.as_array_mut().into_iter().flatten().for_each(descend);
+	} else {
+		descend(value);
+	}
+}
+
+/// The value at `path`, taking the elements at `indices` from the arrays on the way
+fn lookup<'a>(mut value: &'a Value, mut path: &str, indices: &[usize]) -> Option<&'a Value> {
+	let mut indices = indices.iter();
+	loop {
+		let (key, is_array, rest) = split_key(path);
+		value = value.get(key)?;
+		if is_array {
+			value = value.get(*indices.next()?)?;
+		}
+
+		match rest {
+			Some(rest) => path = rest,
+			None => return Some(value),
+		}
+	}
+}
@@ -0,0 +1,198 @@
+//! Record of the packages that were imported, and the files they installed
+use std::{
+	fs::{self, File},
+	io::{self, BufWriter, Write},
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use super::{KeysightEnv, PackMetaData};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum RegistryError {
+	#[error("unable to access the registry of installed packs")]
+	#[diagnostic(code(registry::io::error))]
+	IOError {
+		#[source]
+		reason: io::Error,
+	},
+
+	#[error("malformed registry of installed packs")]
+	#[diagnostic(
+		code(registry::io::json),
+		help("The registry file was modified outside of kspacker, fix or remove it to continue")
+	)]
+	JsonError {
+		#[source]
+		reason: serde_json::Error,
+	},
+
+	#[error("no pack named `{name}` is installed")]
+	#[diagnostic(code(registry::pack::not_installed))]
+	NotInstalled { name: String },
+
+	#[error("unable to remove {}", path.display())]
+	#[diagnostic(code(registry::uninstall::io))]
+	RemoveError {
+		path:   PathBuf,
+		#[source]
+		reason: io::Error,
+	},
+}
+
+/// A file used by an installed pack, relative to the saved directory
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InstalledFile {
+	pub path:    PathBuf,
+	/// Whether the file was written by the pack, or already existed and was kept
+	pub written: bool,
+}
+
+/// A pack that was imported
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InstalledPack {
+	pub metadata:    PackMetaData,
+	/// Blake3 hash of the package file
+	pub source_hash: String,
+	pub installed:   DateTime<Utc>,
+	/// The preset file, relative to the saved directory
+	pub preset:      PathBuf,
+	pub assets:      Vec<InstalledFile>,
+}
+
+impl InstalledPack {
+	pub fn name(&self) -> &str { &self.metadata.name }
+
+	/// The preset and all asset files written by the pack
+	pub fn written_files(&self) -> impl Iterator<Item = &Path> {
+		std::iter::once(self.preset.as_path())
+			.chain(self.assets.iter().filter(|a| a.written).map(|a| a.path.as_path()))
+	}
+
+	fn references(&self, path: &Path) -> bool {
+		self.preset == path || self.assets.iter().any(|a| a.path == path)
+	}
+}
+
+/// The files removed and kept by [`Registry::uninstall`], relative to the saved directory
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Uninstalled {
+	pub removed: Vec<PathBuf>,
+	/// Files the pack wrote that another installed pack references
+	pub shared:  Vec<PathBuf>,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct RegistryFile {
+	packs: Vec<InstalledPack>,
+}
+
+/// The installed packs of a keysight data directory, stored in a json file in the saved
+/// directory
+pub struct Registry {
+	file:  PathBuf,
+	saved: PathBuf,
+	packs: Vec<InstalledPack>,
+}
+
+impl Registry {
+	/// Loads the registry of `env`, which is empty if nothing was imported yet
+	pub fn load(env: &KeysightEnv) -> Result<Self, RegistryError> {
+		let file = env.registry_file();
+		let contents = if file.exists() {
+			let f = File::open(&file).map_err(|reason| RegistryError::IOError { reason })?;
+			serde_json::from_reader(f).map_err(|reason| RegistryError::JsonError { reason })?
+		} else {
+			RegistryFile::default()
+		};
+
+		Ok(Self { file, saved: env.saved_dir(), packs: contents.packs })
+	}
+
+	/// Writes the registry, replacing the previous file only once it is complete
+	pub fn save(&self) -> Result<(), RegistryError> {
+		let io_error = |reason| RegistryError::IOError { reason };
+		fs::create_dir_all(&self.saved).map_err(io_error)?;
+
+		let mut tmp = tempfile::NamedTempFile::new_in(&self.saved).map_err(io_error)?;
+		self.write(&mut tmp)?;
+		tmp.persist(&self.file).map_err(|why| io_error(why.error))?;
+		Ok(())
+	}
+
+	/// The file the registry is saved to
+	pub(crate) fn file(&self) -> &Path { &self.file }
+
+	/// Writes the contents of the registry file to `writer`
+	pub(crate) fn write(&self, writer: impl Write) -> Result<(), RegistryError> {
+		let mut writer = BufWriter::new(writer);
+		serde_json::to_writer_pretty(&mut writer, &RegistryFile { packs: self.packs.clone() })
+			.map_err(|reason| RegistryError::JsonError { reason })?;
+		writer.flush().map_err(|reason| RegistryError::IOError { reason })
+	}
+
+	pub fn packs(&self) -> &[InstalledPack] { &self.packs }
+
+	pub fn find(&self, name: &str) -> Option<&InstalledPack> {
+		self.packs.iter().find(|p| p.name() == name)
+	}
+
+	/// The absolute path of a file recorded in the registry
+	pub fn resolve(&self, path: &Path) -> PathBuf { self.saved.join(path) }
+
+	/// Converts an installed path to the form stored in the registry
+	pub(crate) fn relative(&self, path: &Path) -> PathBuf {
+		path.strip_prefix(&self.saved).unwrap_or(path).to_owned()
+	}
+
+	/// Adds a pack, replacing the record of any pack that installed the same preset
+	pub(crate) fn record(&mut self, pack: InstalledPack) {
+		self.packs.retain(|p| p.preset != pack.preset);
+		self.packs.push(pack);
+	}
+
+	/// Removes the preset and assets written by the pack `name`. Assets another installed pack
+	/// references are kept, and removed once the last pack using them is uninstalled. The
+	/// registry has to be saved afterwards.
+	pub fn uninstall(&mut self, name: &str) -> Result<Uninstalled, RegistryError> {
+		let idx = self
+			.packs
+			.iter()
+			.position(|p| p.name() == name)
+			.ok_or_else(|| RegistryError::NotInstalled { name: name.to_owned() })?;
+
+		let mut result = Uninstalled::default();
+		let pack = self.packs.remove(idx);
+		let written: Vec<PathBuf> = pack.written_files().map(Path::to_owned).collect();
+		for path in &written {
+			if self.packs.iter().any(|p| p.references(path)) {
+				debug!(path=%path.display(), "keeping file used by another pack");
+				result.shared.push(path.clone());
+				continue;
+			}
+
+			let absolute = self.resolve(path);
+			match fs::remove_file(&absolute) {
+				Ok(()) => result.removed.push(path.clone()),
+				Err(why) if why.kind() == io::ErrorKind::NotFound => {
+					debug!(path=%absolute.display(), "file was already removed");
+				},
+				Err(reason) => {
+					self.packs.insert(idx, pack);
+					return Err(RegistryError::RemoveError { path: absolute, reason });
+				},
+			}
+		}
+
+		// the remaining users of a shared file take over removing it
+		for file in self.packs.iter_mut().flat_map(|p| p.assets.iter_mut()) {
+			if result.shared.contains(&file.path) {
+				file.written = true;
+			}
+		}
+
+		Ok(result)
+	}
+}
@@ -0,0 +1,126 @@
+//! Validation of names read from packages before they are used as parts of a path
+use std::fmt;
+
+/// Longest accepted name in bytes. Names become file names, this leaves room for the extension
+/// and the ` (n)` suffix of renamed assets within the 255 byte limit of common file systems,
+/// and keeps the full paths short enough for windows.
+pub const MAX_NAME_LEN: usize = 128;
+
+/// Longest accepted file extension in bytes
+pub const MAX_EXTENSION_LEN: usize = 8;
+
+/// Characters that are separators or otherwise not allowed in file names on windows
+const RESERVED_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Device names windows will not create files for, regardless of the extension
+const RESERVED_NAMES: &[&str] = &[
+	"CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+	"COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+pub enum NameError {
+	#[error("the name is empty")]
+	Empty,
+
+	#[error("the name is longer than {max} bytes")]
+	TooLong { max: usize },
+
+	#[error("the name refers to a directory")]
+	Relative,
+
+	#[error("the name contains the character {0:?}, which is not allowed")]
+	InvalidChar(char),
+
+	#[error("the name contains a control character")]
+	ControlChar,
+
+	#[error("the name is reserved on windows")]
+	ReservedName,
+
+	#[error("the name ends with a dot or space")]
+	TrailingDot,
+
+	#[error("the value is not a blake3 hash")]
+	NotAHash,
+}
+
+/// Which part of the metadata a rejected name came from
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NameField {
+	PresetName,
+	AssetName,
+	AssetHash,
+	AssetExtension,
+}
+
+impl fmt::Display for NameField {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			NameField::PresetName => "preset name",
+			NameField::AssetName => "asset name",
+			NameField::AssetHash => "asset hash",
+			NameField::AssetExtension => "asset extension",
+		})
+	}
+}
+
+/// Checks that `name` can be used as a single path component on every platform
+pub fn check_file_name(name: &str) -> Result<(), NameError> {
+	if name.is_empty() {
+		return Err(NameError::Empty);
+	}
+
+	if name.len() > MAX_NAME_LEN {
+		return Err(NameError::TooLong { max: MAX_NAME_LEN });
+	}
+
+	if name == "." || name == ".." {
+		return Err(NameError::Relative);
+	}
+
+	if let Some(c) = name.chars().find(|c| RESERVED_CHARS.contains(c)) {
+		return Err(NameError::InvalidChar(c));
+	}
+
+	if name.chars().any(char::is_control) {
+		return Err(NameError::ControlChar);
+	}
+
+	let stem = name.split('.').next().unwrap_or(name).trim_end();
+	if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+		return Err(NameError::ReservedName);
+	}
+
+	if name.ends_with('.') || name.ends_with(' ') {
+		return Err(NameError::TrailingDot);
+	}
+
+	Ok(())
+}
+
+/// Returns the lowercase form of a hex encoded blake3 hash
+pub fn normalize_hash(hash: &str) -> Result<String, NameError> {
+	if hash.len() != blake3::OUT_LEN * 2 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return Err(NameError::NotAHash);
+	}
+
+	Ok(hash.to_ascii_lowercase())
+}
+
+/// Returns the lowercase form of a file extension
+pub fn normalize_extension(ext: &str) -> Result<String, NameError> {
+	if ext.is_empty() {
+		return Err(NameError::Empty);
+	}
+
+	if ext.len() > MAX_EXTENSION_LEN {
+		return Err(NameError::TooLong { max: MAX_EXTENSION_LEN });
+	}
+
+	if let Some(c) = ext.chars().find(|c| !c.is_ascii_alphanumeric()) {
+		return Err(NameError::InvalidChar(c));
+	}
+
+	Ok(ext.to_ascii_lowercase())
+}
@@ -0,0 +1,272 @@
+//! Discovery of keysight installations through the steam client
+use std::{
+	fs,
+	iter::Peekable,
+	path::{Path, PathBuf},
+	str::Chars,
+};
+
+use super::{KeysightEnv, Version};
+
+/// The steam app id of keysight
+pub const KEYSIGHT_APP_ID: u32 = 1325730;
+
+/// A node of a valve key-value (`.vdf` / `.acf`) file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Vdf {
+	Value(String),
+	Object(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+	/// Parses a whole file, returning its top level entries as an object
+	pub fn parse(src: &str) -> Option<Vdf> {
+		parse_object(&mut Tokens { chars: src.chars().peekable() }, false).map(Vdf::Object)
+	}
+
+	/// Looks up a child by key, ignoring case like steam does
+	pub fn get(&self, key: &str) -> Option<&Vdf> {
+		match self {
+			Vdf::Object(entries) => {
+				entries.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+			},
+			Vdf::Value(_) => None,
+		}
+	}
+
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Vdf::Value(v) => Some(v),
+			Vdf::Object(_) => None,
+		}
+	}
+}
+
+enum Token {
+	Str(String),
+	Open,
+	Close,
+}
+
+struct Tokens<'a> {
+	chars: Peekable<Chars<'a>>,
+}
+
+impl Iterator for Tokens<'_> {
+	type Item = Token;
+
+	fn next(&mut self) -> Option<Token> {
+		loop {
+			match self.chars.next()? {
+				c if c.is_whitespace() => continue,
+				'/' if self.chars.peek() == Some(&'/') => {
+					for c in self.chars.by_ref() {
+						if c == '\n' {
+							break;
+						}
+					}
+				},
+				'{' => return Some(Token::Open),
+				'}' => return Some(Token::Close),
+				'"' => {
+					let mut s = String::new();
+					loop {
+						match self.chars.next()? {
+							'"' => break,
+							'\\' => match self.chars.next()? {
+								'n' => s.push('\n'),
+								't' => s.push('\t'),
+								other => s.push(other),
+							},
+							other => s.push(other),
+						}
+					}
+					return Some(Token::Str(s));
+				},
+				first => {
+					let mut s = String::from(first);
+					while let Some(&c) = self.chars.peek() {
+						if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+							break;
+						}
+						s.push(c);
+						self.chars.next();
+					}
+					return Some(Token::Str(s));
+				},
+			}
+		}
+	}
+}
+
+fn parse_object(tokens: &mut Tokens, nested: bool) -> Option<Vec<(String, Vdf)>> {
+	let mut entries = Vec::new();
+	loop {
+		match tokens.next() {
+			None if !nested => return Some(entries),
+			Some(Token::Close) if nested => return Some(entries),
+			Some(Token::Str(key)) => match tokens.next()? {
+				Token::Str(value) => entries.push((key, Vdf::Value(value))),
+				Token::Open => entries.push((key, Vdf::Object(parse_object(tokens, true)?))),
+				Token::Close => return None,
+			},
+			_ => return None,
+		}
+	}
+}
+
+/// A keysight installation whose default presets could be read
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Installation {
+	pub install_root: PathBuf,
+	pub version:      Version,
+}
+
+/// The locations steam clients and libraries are usually found at
+pub fn default_steam_roots() -> Vec<PathBuf> {
+	let mut roots = Vec::new();
+
+	#[cfg(windows)]
+	{
+		roots.push(PathBuf::from(r"C:\Program Files (x86)\Steam"));
+		roots.push(PathBuf::from(r"C:\Program Files\Steam"));
+		for drive in 'C'..='Z' {
+			roots.push(PathBuf::from(format!(r"{}:\SteamLibrary", drive)));
+			roots.push(PathBuf::from(format!(r"{}:\Steam", drive)));
+		}
+	}
+
+	#[cfg(target_os = "linux")]
+	if let Some(home) = dirs::home_dir() {
+		roots.push(home.join(".steam").join("steam"));
+		roots.push(home.join(".local").join("share").join("Steam"));
+		roots.push(
+			home.join(".var")
+				.join("app")
+				.join("com.valvesoftware.Steam")
+				.join(".local")
+				.join("share")
+				.join("Steam"),
+		);
+	}
+
+	roots
+}
+
+/// All library folders of the steam client at `steam_root`, including the root itself
+pub fn library_folders(steam_root: &Path) -> Vec<PathBuf> {
+	let mut folders = vec![steam_root.to_owned()];
+
+	let vdf = fs::read_to_string(steam_root.join("steamapps").join("libraryfolders.vdf"))
+		.ok()
+		.and_then(|src| Vdf::parse(&src));
+
+	if let Some(Vdf::Object(entries)) = vdf.as_ref().and_then(|v| v.get("libraryfolders")) {
+		for (key, entry) in entries {
+			// other keys hold statistics in older versions of the file
+			if !key.bytes().all(|b| b.is_ascii_digit()) {
+				continue;
+			}
+
+			// older versions store the path directly instead of in an object
+			let path = match entry {
+				Vdf::Value(path) => Some(path.as_str()),
+				Vdf::Object(_) => entry.get("path").and_then(Vdf::as_str),
+			};
+
+			if let Some(path) = path {
+				let path = PathBuf::from(path);
+				if !folders.contains(&path) {
+					folders.push(path);
+				}
+			}
+		}
+	}
+
+	folders
+}
+
+/// The keysight install directory inside a steam library, if it is installed there
+pub fn keysight_in_library(library: &Path) -> Option<PathBuf> {
+	let steamapps = library.join("steamapps");
+
+	let install_dir = fs::read_to_string(
+		steamapps.join(format!("appmanifest_{}.acf", KEYSIGHT_APP_ID)),
+	)
+	.ok()
+	.and_then(|src| Vdf::parse(&src))
+	.and_then(|manifest| {
+		manifest.get("AppState")?.get("installdir")?.as_str().map(str::to_owned)
+	})
+	.unwrap_or_else(|| String::from("Keysight"));
+
+	let path = steamapps.join("common").join(install_dir);
+	path.is_dir().then_some(path)
+}
+
+/// The local app data directory inside keysight's proton prefix of a steam library
+pub fn proton_data_dir(library: &Path) -> PathBuf {
+	library
+		.join("steamapps")
+		.join("compatdata")
+		.join(KEYSIGHT_APP_ID.to_string())
+		.join("pfx")
+		.join("drive_c")
+		.join("users")
+		.join("steamuser")
+		.join("AppData")
+		.join("Local")
+}
+
+/// The library folders of all given steam roots, without duplicates
+fn unique_libraries(steam_roots: &[PathBuf]) -> Vec<PathBuf> {
+	let mut libraries: Vec<PathBuf> = Vec::new();
+	for root in steam_roots {
+		for library in library_folders(root) {
+			// the default roots commonly link to each other
+			let library = library.canonicalize().unwrap_or(library);
+			if !libraries.contains(&library) {
+				libraries.push(library);
+			}
+		}
+	}
+
+	libraries
+}
+
+/// Finds keysight installations in the libraries of the given steam roots, keeping only those
+/// that pass the same check as [`get_ks_version`](crate::get_ks_version)
+pub fn detect_installations(steam_roots: &[PathBuf]) -> Vec<Installation> {
+	let mut found = Vec::new();
+	for library in unique_libraries(steam_roots) {
+		if let Some(install_root) = keysight_in_library(&library) {
+			match crate::get_ks_version(&install_root) {
+				Ok(version) => {
+					debug!(install_root=%install_root.display(), "found installation");
+					found.push(Installation { install_root, version });
+				},
+				Err(why) => {
+					debug!(install_root=%install_root.display(), %why, "rejected installation");
+				},
+			}
+		}
+	}
+
+	found
+}
+
+/// Finds keysight installations that are run through proton by any of the given steam clients
+pub fn discover_proton(steam_roots: &[PathBuf]) -> Vec<KeysightEnv> {
+	let mut found = Vec::new();
+	for library in unique_libraries(steam_roots) {
+		let data_root = proton_data_dir(&library);
+		if let Some(install_root) = keysight_in_library(&library) {
+			if data_root.is_dir() {
+				debug!(library=%library.display(), "found proton installation");
+				found.push(KeysightEnv::new(install_root, data_root));
+			}
+		}
+	}
+
+	found
+}
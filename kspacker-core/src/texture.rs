@@ -0,0 +1,525 @@
+//! Detection of the file format of textures, validation of their contents and optimisation
+use std::{
+	fmt,
+	fs::File,
+	io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+	path::Path,
+};
+
+use image::{
+	codecs::{
+		jpeg::JpegEncoder,
+		png::{CompressionType, FilterType, PngEncoder},
+	},
+	error::DecodingError,
+	DynamicImage,
+	ImageDecoder,
+	ImageError,
+	ImageReader,
+};
+
+/// Extensions probed for a texture name, in order of preference when several files exist. The
+/// formats keysight loads natively come first, sprite sheets of pulse stencils are plain images
+/// in any of them.
+pub const DEFAULT_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "tga", "bmp", "exr", "webp"];
+
+/// The format of a texture, as detected from its contents
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ImageFormat {
+	Png,
+	Jpeg,
+	Tga,
+	Bmp,
+	Exr,
+	WebP,
+	/// Not a known format, or packed before formats were detected
+	#[default]
+	Unknown,
+}
+
+impl ImageFormat {
+	/// Detects the format from the first bytes of a file. Tga files have no signature, so they
+	/// are recognised by a plausible header if the extension says so.
+	pub fn detect(header: &[u8], extension: &str) -> Self {
+		if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+			ImageFormat::Png
+		} else if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+			ImageFormat::Jpeg
+		} else if header.starts_with(&[0x76, 0x2F, 0x31, 0x01]) {
+			ImageFormat::Exr
+		} else if header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"WEBP" {
+			ImageFormat::WebP
+		} else if header.starts_with(b"BM") && header.len() >= 14 {
+			ImageFormat::Bmp
+		} else if extension.eq_ignore_ascii_case("tga") && is_tga_header(header) {
+			ImageFormat::Tga
+		} else {
+			ImageFormat::Unknown
+		}
+	}
+
+	/// The format for the decoder, unknown formats cannot be decoded
+	fn decoder_format(&self) -> Option<image::ImageFormat> {
+		match self {
+			ImageFormat::Png => Some(image::ImageFormat::Png),
+			ImageFormat::Jpeg => Some(image::ImageFormat::Jpeg),
+			ImageFormat::Tga => Some(image::ImageFormat::Tga),
+			ImageFormat::Bmp => Some(image::ImageFormat::Bmp),
+			ImageFormat::Exr => Some(image::ImageFormat::OpenExr),
+			ImageFormat::WebP => Some(image::ImageFormat::WebP),
+			ImageFormat::Unknown => None,
+		}
+	}
+
+	/// Whether files of this format are expected to have `extension`
+	pub fn matches_extension(&self, extension: &str) -> bool {
+		let extension = extension.to_ascii_lowercase();
+		match self {
+			ImageFormat::Png => extension == "png",
+			ImageFormat::Jpeg => extension == "jpg" || extension == "jpeg",
+			ImageFormat::Tga => extension == "tga",
+			ImageFormat::Bmp => extension == "bmp",
+			ImageFormat::Exr => extension == "exr",
+			ImageFormat::WebP => extension == "webp",
+			ImageFormat::Unknown => false,
+		}
+	}
+}
+
+impl fmt::Display for ImageFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			ImageFormat::Png => "PNG",
+			ImageFormat::Jpeg => "JPEG",
+			ImageFormat::Tga => "TGA",
+			ImageFormat::Bmp => "BMP",
+			ImageFormat::Exr => "OpenEXR",
+			ImageFormat::WebP => "WebP",
+			ImageFormat::Unknown => "Unknown",
+		})
+	}
+}
+
+/// Checks the colour map and image type fields of a tga header
+fn is_tga_header(header: &[u8]) -> bool {
+	const HEADER_LEN: usize = 18;
+	if header.len() < HEADER_LEN {
+		return false;
+	}
+
+	let colour_map = header[1];
+	let image_type = header[2];
+	let bits_per_pixel = header[16];
+	colour_map <= 1
+		&& matches!(image_type, 1 | 2 | 3 | 9 | 10 | 11)
+		&& matches!(bits_per_pixel, 8 | 15 | 16 | 24 | 32)
+}
+
+/// Textures wider or higher than this use a lot of video memory, and are slow to load in keysight
+pub const RECOMMENDED_MAX_SIZE: u32 = 4096;
+
+/// Textures whose pixels take more memory than this are never decoded at once by
+/// [`ImageInfo::read`]. Png files are decoded row by row and exr files block by block, the other
+/// formats are only checked to be as long as their headers say, which still rejects truncated
+/// files.
+pub const MAX_DECODE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Properties of a texture, read by decoding it when packing
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct ImageInfo {
+	pub width:     u32,
+	pub height:    u32,
+	pub channels:  u8,
+	/// Bits per channel
+	pub bit_depth: u8,
+}
+
+impl ImageInfo {
+	/// Decodes a whole texture, which fails for truncated or corrupt files
+	pub fn decode(data: &[u8], format: ImageFormat) -> Result<Self, ImageError> {
+		decode(data, format).map(|image| Self::of(&image))
+	}
+
+	/// Decodes a whole texture from `reader`, which fails for truncated or corrupt files. Textures
+	/// larger than [`MAX_DECODE_BYTES`] are not kept in memory completely, see there.
+	pub fn read<R: BufRead + Seek>(mut reader: R, format: ImageFormat) -> Result<Self, ImageError> {
+		let decoder = image_reader(&mut reader, format)?.into_decoder()?;
+
+		if decoder.total_bytes() <= MAX_DECODE_BYTES {
+			return DynamicImage::from_decoder(decoder).map(|image| Self::of(&image));
+		}
+
+		let (width, height) = decoder.dimensions();
+		let info = Self::from_colour(width, height, decoder.color_type());
+		drop(decoder);
+		reader.rewind()?;
+		match format {
+			ImageFormat::Png => validate_png_rows(reader)?,
+			ImageFormat::Exr => validate_exr_blocks(reader)?,
+			ImageFormat::Bmp => validate_bmp_length(reader)?,
+			ImageFormat::Tga => validate_tga_packets(reader, &info)?,
+			ImageFormat::Jpeg => validate_jpeg_end(reader)?,
+			ImageFormat::WebP => validate_webp_length(reader)?,
+			ImageFormat::Unknown => {
+				return Err(ImageError::Decoding(DecodingError::new(
+					image::error::ImageFormatHint::Unknown,
+					"too large to validate in an unknown format",
+				)))
+			},
+		}
+		Ok(info)
+	}
+
+	/// Reads the properties of a texture from its header, without checking its pixels
+	pub fn read_header(reader: impl BufRead + Seek, format: ImageFormat) -> Result<Self, ImageError> {
+		let decoder = image_reader(reader, format)?.into_decoder()?;
+		let (width, height) = decoder.dimensions();
+		Ok(Self::from_colour(width, height, decoder.color_type()))
+	}
+
+	pub fn of(image: &DynamicImage) -> Self { Self::from_colour(image.width(), image.height(), image.color()) }
+
+	fn from_colour(width: u32, height: u32, colour: image::ColorType) -> Self {
+		let channels = colour.channel_count();
+		Self {
+			width,
+			height,
+			channels,
+			bit_depth: (colour.bits_per_pixel() / u16::from(channels)) as u8,
+		}
+	}
+
+	/// Whether both sides are a power of two, which keysight needs to generate mipmaps
+	pub fn is_power_of_two(&self) -> bool { self.width.is_power_of_two() && self.height.is_power_of_two() }
+
+	/// Whether a side exceeds [`RECOMMENDED_MAX_SIZE`]
+	pub fn is_oversized(&self) -> bool {
+		self.width > RECOMMENDED_MAX_SIZE || self.height > RECOMMENDED_MAX_SIZE
+	}
+}
+
+impl fmt::Display for ImageInfo {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}x{}, {} channel(s), {} bit", self.width, self.height, self.channels, self.bit_depth)
+	}
+}
+
+/// A problem with a texture that keysight copes with, so it is packed anyway
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TextureWarning {
+	/// The contents are in another format than the extension says
+	MismatchedExtension(ImageFormat),
+	/// Keysight cannot generate mipmaps for the texture
+	NotPowerOfTwo(ImageInfo),
+	/// A side exceeds [`RECOMMENDED_MAX_SIZE`]
+	Oversized(ImageInfo),
+}
+
+impl TextureWarning {
+	/// Warns if the contents in `format` do not match `extension`
+	pub fn of_format(format: ImageFormat, extension: &str) -> Option<Self> {
+		(!format.matches_extension(extension)).then_some(TextureWarning::MismatchedExtension(format))
+	}
+
+	/// The warnings about the size of `image`
+	pub fn of_image(image: ImageInfo) -> Vec<Self> {
+		let mut warnings = Vec::new();
+		if !image.is_power_of_two() {
+			warnings.push(TextureWarning::NotPowerOfTwo(image));
+		}
+		if image.is_oversized() {
+			warnings.push(TextureWarning::Oversized(image));
+		}
+		warnings
+	}
+}
+
+impl fmt::Display for TextureWarning {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			TextureWarning::MismatchedExtension(ImageFormat::Unknown) => {
+				f.write_str("contents are not in a known image format")
+			},
+			TextureWarning::MismatchedExtension(format) => {
+				write!(f, "contents are {}, which does not match the extension", format)
+			},
+			TextureWarning::NotPowerOfTwo(image) => {
+				write!(f, "{} is not a power of two, keysight cannot generate mipmaps for it", image)
+			},
+			TextureWarning::Oversized(image) => {
+				write!(f, "{} is larger than the recommended {} px", image, RECOMMENDED_MAX_SIZE)
+			},
+		}
+	}
+}
+
+/// The warnings about the texture at `path` that its header shows. Textures that cannot be read
+/// have none, packing rejects them.
+pub fn probe(path: &Path, extension: &str) -> Vec<TextureWarning> {
+	let mut reader = match File::open(path) {
+		Ok(file) => BufReader::new(file),
+		Err(_) => return Vec::new(),
+	};
+	let format = match reader.fill_buf() {
+		Ok(head) => ImageFormat::detect(head, extension),
+		Err(_) => return Vec::new(),
+	};
+
+	let mut warnings: Vec<_> = TextureWarning::of_format(format, extension).into_iter().collect();
+	if let Ok(image) = ImageInfo::read_header(reader, format) {
+		warnings.extend(TextureWarning::of_image(image));
+	}
+	warnings
+}
+
+/// A reader decoding `format`, which guesses the format if it is unknown
+fn image_reader<R: BufRead + Seek>(reader: R, format: ImageFormat) -> Result<ImageReader<R>, ImageError> {
+	match format.decoder_format() {
+		Some(decoder_format) => Ok(ImageReader::with_format(reader, decoder_format)),
+		None => Ok(ImageReader::new(reader).with_guessed_format()?),
+	}
+}
+
+/// Decodes a png one row at a time, checking every chunk up to its end
+fn validate_png_rows(reader: impl BufRead + Seek) -> Result<(), ImageError> {
+	let png_error =
+		|why: png::DecodingError| ImageError::Decoding(DecodingError::new(image::ImageFormat::Png.into(), why));
+
+	let mut png = png::Decoder::new(reader).read_info().map_err(png_error)?;
+	while png.next_row().map_err(png_error)?.is_some() {}
+	png.finish().map_err(png_error)
+}
+
+/// Decompresses an exr one block at a time, checking every chunk up to the last one
+fn validate_exr_blocks(reader: impl Read + Seek) -> Result<(), ImageError> {
+	use exr::block::reader::ChunksReader;
+
+	let exr_error =
+		|why: exr::error::Error| ImageError::Decoding(DecodingError::new(image::ImageFormat::OpenExr.into(), why));
+
+	let chunks = exr::block::read(reader, false).and_then(|blocks| blocks.all_chunks(false)).map_err(exr_error)?;
+	for block in chunks.sequential_decompressor(false) {
+		block.map_err(exr_error)?;
+	}
+	Ok(())
+}
+
+/// Checks that a bmp is long enough for its pixels. Uncompressed rows have a known length,
+/// compressed pixels take the image size of the header.
+fn validate_bmp_length(mut reader: impl Read + Seek) -> Result<(), ImageError> {
+	const CORE_HEADER_LEN: u32 = 12;
+
+	let mut header = [0u8; 54];
+	reader.read_exact(&mut header)?;
+	let u16_at = |pos: usize| u64::from(u16::from_le_bytes([header[pos], header[pos + 1]]));
+	let u32_at = |pos: usize| u32::from_le_bytes(header[pos..pos + 4].try_into().unwrap());
+
+	let data_offset = u64::from(u32_at(10));
+	let data_len = if u32_at(14) == CORE_HEADER_LEN {
+		// the old os/2 header has 16 bit sizes and no compression
+		row_len(u16_at(18), u16_at(24)).saturating_mul(u16_at(20))
+	} else {
+		let width = u64::from((u32_at(18) as i32).unsigned_abs());
+		let height = u64::from((u32_at(22) as i32).unsigned_abs());
+		match u32_at(30) {
+			// rgb and bit fields, the masks of which are part of the header
+			0 | 3 | 6 => row_len(width, u16_at(28)).saturating_mul(height),
+			_ => u64::from(u32_at(34)),
+		}
+	};
+
+	check_length(reader, data_offset.saturating_add(data_len), image::ImageFormat::Bmp)
+}
+
+/// The length of a bmp row, which is padded to four bytes
+fn row_len(width: u64, bits_per_pixel: u64) -> u64 { (width * bits_per_pixel).div_ceil(32) * 4 }
+
+/// Checks that a tga holds all pixels of `image`, walking the packets of run length encoded ones
+fn validate_tga_packets(mut reader: impl BufRead + Seek, image: &ImageInfo) -> Result<(), ImageError> {
+	const RUN_LENGTH_ENCODED: u8 = 8;
+
+	let mut header = [0u8; 18];
+	reader.read_exact(&mut header)?;
+	let id_len = u64::from(header[0]);
+	let colour_map_len = match header[1] {
+		1 => u64::from(u16::from_le_bytes([header[5], header[6]])) * u64::from(header[7]).div_ceil(8),
+		_ => 0,
+	};
+	let pixel_len = u64::from(header[16]).div_ceil(8);
+	let pixels = u64::from(image.width) * u64::from(image.height);
+
+	let data_offset = header.len() as u64 + id_len + colour_map_len;
+	if header[2] & RUN_LENGTH_ENCODED == 0 {
+		return check_length(reader, data_offset + pixels * pixel_len, image::ImageFormat::Tga);
+	}
+
+	reader.seek(SeekFrom::Start(data_offset))?;
+	let mut remaining = pixels;
+	while remaining > 0 {
+		let mut packet = [0u8];
+		if reader.read(&mut packet)? == 0 {
+			return Err(truncated(image::ImageFormat::Tga));
+		}
+
+		// the high bit marks a run of one repeated pixel, otherwise every pixel follows
+		let count = u64::from(packet[0] & 0x7F) + 1;
+		let len = if packet[0] & 0x80 != 0 { pixel_len } else { count * pixel_len };
+		if io::copy(&mut (&mut reader).take(len), &mut io::sink())? < len {
+			return Err(truncated(image::ImageFormat::Tga));
+		}
+		remaining = remaining.saturating_sub(count);
+	}
+	Ok(())
+}
+
+/// Checks that a jpeg has an end of image marker near its end, which truncated files lack
+fn validate_jpeg_end(mut reader: impl Read + Seek) -> Result<(), ImageError> {
+	// some writers append data after the marker
+	const TAIL_LEN: u64 = 64 * 1024;
+
+	let len = reader.seek(SeekFrom::End(0))?;
+	reader.seek(SeekFrom::Start(len.saturating_sub(TAIL_LEN)))?;
+	let mut tail = Vec::new();
+	reader.read_to_end(&mut tail)?;
+
+	match tail.windows(2).any(|marker| marker == [0xFF, 0xD9]) {
+		true => Ok(()),
+		false => Err(truncated(image::ImageFormat::Jpeg)),
+	}
+}
+
+/// Checks that a webp is as long as its riff header says
+fn validate_webp_length(mut reader: impl Read + Seek) -> Result<(), ImageError> {
+	let mut header = [0u8; 8];
+	reader.read_exact(&mut header)?;
+	let riff_len = u64::from(u32::from_le_bytes(header[4..8].try_into().unwrap()));
+	check_length(reader, header.len() as u64 + riff_len, image::ImageFormat::WebP)
+}
+
+/// Fails with [`truncated`] if the file is shorter than `len` bytes
+fn check_length(mut reader: impl Seek, len: u64, format: image::ImageFormat) -> Result<(), ImageError> {
+	match reader.seek(SeekFrom::End(0))? >= len {
+		true => Ok(()),
+		false => Err(truncated(format)),
+	}
+}
+
+/// The error for a file that ends before the pixels its header describes
+fn truncated(format: image::ImageFormat) -> ImageError {
+	ImageError::Decoding(DecodingError::new(format.into(), "the file ends before all of its pixels"))
+}
+
+/// Decodes a whole texture, which fails for truncated or corrupt files
+pub fn decode(data: &[u8], format: ImageFormat) -> Result<DynamicImage, ImageError> {
+	match format.decoder_format() {
+		Some(decoder_format) => image::load_from_memory_with_format(data, decoder_format),
+		// fails with an unsupported format error, unless detection missed a format
+		None => image::load_from_memory(data),
+	}
+}
+
+/// Settings of the optional texture optimisation when packing
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Optimise {
+	/// Textures with a side longer than this are scaled down to fit, keeping their aspect ratio.
+	/// This is the only lossy step.
+	pub max_size: Option<u32>,
+}
+
+/// A texture rewritten by [`optimise`]
+#[derive(Debug, Clone)]
+pub struct Optimised {
+	pub data:  Vec<u8>,
+	pub image: ImageInfo,
+}
+
+/// Recompresses png textures losslessly and strips exif and icc metadata, scaling textures
+/// down to [`Optimise::max_size`]. Returns `None` if the original is already as small.
+pub fn optimise(
+	data: &[u8],
+	format: ImageFormat,
+	image: &DynamicImage,
+	options: &Optimise,
+) -> Result<Option<Optimised>, ImageError> {
+	let downscale = options.max_size.filter(|max| image.width() > *max || image.height() > *max);
+	let scaled;
+	let image = match downscale {
+		Some(max) => {
+			scaled = image.resize(max, max, image::imageops::FilterType::Lanczos3);
+			&scaled
+		},
+		None => image,
+	};
+
+	let optimised = match format {
+		// encoding the pixels drops all ancillary chunks, including exif and icc profiles
+		ImageFormat::Png => {
+			let mut out = Vec::new();
+			let encoder = PngEncoder::new_with_quality(&mut out, CompressionType::Best, FilterType::Adaptive);
+			image.write_with_encoder(encoder)?;
+			out
+		},
+		// reencoding a jpeg is lossy, so unless it is scaled only its metadata is removed
+		ImageFormat::Jpeg if downscale.is_none() => match strip_jpeg_metadata(data) {
+			Some(stripped) => stripped,
+			None => return Ok(None),
+		},
+		ImageFormat::Jpeg => {
+			let mut out = Vec::new();
+			let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+			rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut out, 90))?;
+			out
+		},
+		_ => match (downscale, format.decoder_format()) {
+			(Some(_), Some(encoder_format)) => {
+				let mut out = Cursor::new(Vec::new());
+				image.write_to(&mut out, encoder_format)?;
+				out.into_inner()
+			},
+			_ => return Ok(None),
+		},
+	};
+
+	if downscale.is_none() && optimised.len() >= data.len() {
+		return Ok(None);
+	}
+
+	Ok(Some(Optimised { data: optimised, image: ImageInfo::of(image) }))
+}
+
+/// Removes the APP1 (exif and xmp) and APP2 (icc profile) segments of a jpeg, returning `None`
+/// if it has none or is malformed
+fn strip_jpeg_metadata(data: &[u8]) -> Option<Vec<u8>> {
+	const START_OF_SCAN: u8 = 0xDA;
+	const APP1: u8 = 0xE1;
+	const APP2: u8 = 0xE2;
+
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(data.get(..2)?);
+	let mut pos = 2;
+	let mut stripped = false;
+
+	loop {
+		let marker = data.get(pos..pos + 2)?;
+		if marker[0] != 0xFF {
+			return None;
+		}
+
+		// the entropy coded data after the start of scan has no length, it is copied as is
+		if marker[1] == START_OF_SCAN {
+			out.extend_from_slice(&data[pos..]);
+			break;
+		}
+
+		let len = usize::from(u16::from_be_bytes(data.get(pos + 2..pos + 4)?.try_into().ok()?));
+		let segment = data.get(pos..pos + 2 + len)?;
+		if marker[1] == APP1 || marker[1] == APP2 {
+			stripped = true;
+		} else {
+			out.extend_from_slice(segment);
+		}
+		pos += 2 + len;
+	}
+
+	stripped.then_some(out)
+}
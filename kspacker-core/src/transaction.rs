@@ -0,0 +1,111 @@
+//! All-or-nothing installation of files
+use std::{
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+/// Files are written to a staging directory first and only moved into place on commit. Files
+/// that get overwritten are kept as backups until the commit succeeded, so a failing commit
+/// can restore the previous state.
+///
+/// The staging directory has to be on the same volume as the targets for the moves to be
+/// renames.
+pub struct Transaction {
+	staging: TempDir,
+	staged:  Vec<(PathBuf, PathBuf)>,
+}
+
+/// A completed step of a commit, in the order it has to be undone
+enum Step {
+	CreatedDir(PathBuf),
+	BackedUp { target: PathBuf, backup: PathBuf },
+	Placed(PathBuf),
+}
+
+impl Transaction {
+	/// Starts a transaction staging in a new directory inside `dir`
+	pub fn new(dir: &Path) -> io::Result<Self> {
+		fs::create_dir_all(dir)?;
+		let staging = tempfile::Builder::new().prefix(".kspacker-import-").tempdir_in(dir)?;
+		Ok(Self { staging, staged: Vec::new() })
+	}
+
+	/// Creates a staged file that will be moved to `target` on commit
+	pub fn stage(&mut self, target: PathBuf) -> io::Result<File> {
+		let idx = match self.staged.iter().position(|(_, t)| *t == target) {
+			Some(idx) => idx,
+			None => {
+				let staged = self.staging.path().join(format!("staged-{}", self.staged.len()));
+				self.staged.push((staged, target));
+				self.staged.len() - 1
+			},
+		};
+
+		File::create(&self.staged[idx].0)
+	}
+
+	/// Moves all staged files into place, restoring everything if any of them fails
+	pub fn commit(self) -> io::Result<()> {
+		let mut done = Vec::new();
+
+		for (idx, (staged, target)) in self.staged.iter().enumerate() {
+			if let Err(why) = self.place(idx, staged, target, &mut done) {
+				warn!(target=%target.display(), %why, "failed to install file, rolling back");
+				Self::rollback(done);
+				return Err(why);
+			}
+		}
+
+		debug!(files = self.staged.len(), "committed transaction");
+		Ok(())
+	}
+
+	fn place(&self, idx: usize, staged: &Path, target: &Path, done: &mut Vec<Step>) -> io::Result<()> {
+		if let Some(parent) = target.parent() {
+			let mut missing = Vec::new();
+			let mut dir = parent;
+			while !dir.exists() {
+				missing.push(dir.to_owned());
+				match dir.parent() {
+					Some(up) => dir = up,
+					None => break,
+				}
+			}
+
+			for dir in missing.into_iter().rev() {
+				fs::create_dir(&dir)?;
+				done.push(Step::CreatedDir(dir));
+			}
+		}
+
+		if target.exists() {
+			let backup = self.staging.path().join(format!("backup-{}", idx));
+			fs::rename(target, &backup)?;
+			done.push(Step::BackedUp { target: target.to_owned(), backup });
+		}
+
+		fs::rename(staged, target)?;
+		done.push(Step::Placed(target.to_owned()));
+		Ok(())
+	}
+
+	fn rollback(done: Vec<Step>) {
+		for step in done.into_iter().rev() {
+			let result = match &step {
+				Step::CreatedDir(dir) => fs::remove_dir(dir),
+				Step::BackedUp { target, backup } => fs::rename(backup, target),
+				Step::Placed(target) => fs::remove_file(target),
+			};
+
+			if let Err(why) = result {
+				let path = match &step {
+					Step::CreatedDir(path) | Step::BackedUp { target: path, .. } | Step::Placed(path) => path,
+				};
+				error!(path=%path.display(), %why, "failed to roll back");
+			}
+		}
+	}
+}
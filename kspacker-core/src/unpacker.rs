@@ -0,0 +1,759 @@
+use std::{
+	collections::BTreeSet,
+	fmt,
+	fs::File,
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use zip::{read::ZipFile, result::ZipError, ZipArchive};
+
+use super::{
+	format::{self, FormatVersion, PackageMetaData},
+	limits::{Limit, UnpackLimits},
+	progress::{Progress, Tracker},
+	references,
+	registry::{InstalledFile, InstalledPack, Registry, RegistryError},
+	sanitize::{self, NameError, NameField},
+	transaction::Transaction,
+	BundleInfo,
+	KeysightEnv,
+	MetaEntry,
+	PackMetaData,
+};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[non_exhaustive]
+pub enum UnpackError {
+	#[error("package does not contain asset {name}")]
+	#[diagnostic(code(unpack::package::asset_missing))]
+	AssetNotFound { name: String },
+
+	#[error("unable to read package")]
+	#[diagnostic(code(unpack::io::error))]
+	PackIOError {
+		#[source]
+		reason: io::Error,
+	},
+
+	#[error("unable to parse package")]
+	#[diagnostic(code(unpack::io::zip))]
+	ZipIOError {
+		#[source]
+		reason: zip::result::ZipError,
+	},
+
+	#[error("malformed json in package")]
+	#[diagnostic(code(unpack::io::json))]
+	JsonError {
+		#[source]
+		reason: serde_json::Error,
+	},
+
+	#[error("asset {name} does not match its hash")]
+	#[diagnostic(
+		code(unpack::package::hash_mismatch),
+		help("The package is corrupted or was modified after packing, expected {expected} but got {actual}")
+	)]
+	HashMismatch { name: String, expected: String, actual: String },
+
+	#[error("package format {version} requires {}", requirement(.requires))]
+	#[diagnostic(
+		code(unpack::package::unsupported_format),
+		help("The package was made with a newer kspacker, please update to import it")
+	)]
+	UnsupportedFormat { version: FormatVersion, requires: Option<String> },
+
+	#[error("package is a bundle of {presets} presets")]
+	#[diagnostic(
+		code(unpack::package::bundle),
+		help("Load it as a bundle to choose the presets to import")
+	)]
+	IsBundle { presets: usize },
+
+	#[error("package contains an unsafe {field} {value:?}")]
+	#[diagnostic(
+		code(unpack::package::unsafe_name),
+		help("The package could write outside of the keysight directories and was rejected")
+	)]
+	UnsafeName {
+		field:  NameField,
+		value:  String,
+		#[source]
+		reason: NameError,
+	},
+
+	#[error("{entry} exceeds the {limit} limit of {max}")]
+	#[diagnostic(
+		code(unpack::package::limit),
+		help("The package may be a zip bomb. If you trust it, raise the limit and try again")
+	)]
+	LimitExceeded { entry: String, limit: Limit, max: u64 },
+
+	#[error("unable to read the installed file {}", path.display())]
+	#[diagnostic(code(unpack::io::existing))]
+	ExistingIOError {
+		path:   PathBuf,
+		#[source]
+		reason: io::Error,
+	},
+
+	#[error(transparent)]
+	#[diagnostic(transparent)]
+	Registry(#[from] RegistryError),
+
+	#[error("import was cancelled")]
+	#[diagnostic(code(unpack::cancelled), help("No files have been changed"))]
+	Cancelled,
+
+	#[error("unable to install package")]
+	#[diagnostic(code(unpack::io::install), help("All files written so far have been restored"))]
+	InstallError {
+		#[source]
+		reason: io::Error,
+	},
+}
+
+fn requirement(requires: &Option<String>) -> String {
+	match requires {
+		Some(version) => format!("kspacker ≥ {}", version),
+		None => String::from("a newer kspacker"),
+	}
+}
+
+/// Opens a packed preset file
+pub struct Unpacker {
+	env:    KeysightEnv,
+	path:   PathBuf,
+	limits: UnpackLimits,
+}
+
+impl Unpacker {
+	pub fn new(env: KeysightEnv, src: impl AsRef<Path>) -> Self {
+		Self { env, path: src.as_ref().to_owned(), limits: UnpackLimits::default() }
+	}
+
+	/// Replaces the default limits for loading and unpacking
+	pub fn limits(mut self, limits: UnpackLimits) -> Self {
+		self.limits = limits;
+		self
+	}
+
+	/// Checks the archive against the limits and reads its metadata
+	fn read(&self) -> Result<PackageMetaData, UnpackError> {
+		let fopen = File::open(&self.path).map_err(|reason| UnpackError::PackIOError { reason })?;
+		let mut zipfile = zip::read::ZipArchive::new(fopen)
+			.map_err(|reason| UnpackError::ZipIOError { reason })?;
+
+		// the sizes declared in the archive allow rejecting most bombs before decompressing
+		self.limits.check_entries(zipfile.len())?;
+		let mut total = 0u64;
+		for idx in 0..zipfile.len() {
+			let entry =
+				zipfile.by_index_raw(idx).map_err(|reason| UnpackError::ZipIOError { reason })?;
+			self.limits.check_entry(entry.name(), entry.size(), entry.compressed_size())?;
+			total = total.saturating_add(entry.size());
+		}
+		self.limits.check_total(total)?;
+
+		let mut metadata_file = zipfile
+			.by_name(format::METADATA_ENTRY)
+			.map_err(|reason| UnpackError::ZipIOError { reason })?;
+		let mut metadata_buf = Vec::new();
+		let compressed = metadata_file.compressed_size();
+		self.limits.copy_entry(format::METADATA_ENTRY, compressed, &mut metadata_file, &mut metadata_buf)?;
+
+		format::read_metadata(&metadata_buf)
+	}
+
+	/// Checks the metadata of the preset stored as `preset_entry` for conflicts
+	fn packed_file(&self, mut metadata: PackMetaData, preset_entry: String) -> Result<PackedFile, UnpackError> {
+		sanitize_metadata(&mut metadata)?;
+		let report = ConflictReport::new(&self.env, &metadata)?;
+
+		Ok(PackedFile {
+			env: self.env.clone(),
+			path: self.path.clone(),
+			limits: self.limits,
+			preset_entry,
+			metadata,
+			report,
+		})
+	}
+
+	/// Loads the metadata of a single preset package and checks for conflicts
+	pub fn load(self) -> Result<PackedFile, UnpackError> {
+		match self.read()? {
+			PackageMetaData::Preset(metadata) => self.packed_file(metadata, format::PRESET_ENTRY.to_owned()),
+			PackageMetaData::Bundle(bundle) => Err(UnpackError::IsBundle { presets: bundle.presets.len() }),
+		}
+	}
+
+	/// Loads a bundle and checks all of its presets for conflicts. A single preset package is
+	/// loaded as a bundle of one.
+	pub fn load_bundle(self) -> Result<PackedBundle, UnpackError> {
+		match self.read()? {
+			PackageMetaData::Preset(metadata) => {
+				let info = BundleInfo {
+					name:        metadata.name.clone(),
+					author:      metadata.author.clone(),
+					description: metadata.description.clone(),
+					packed:      metadata.packed,
+				};
+				let preset = self.packed_file(metadata, format::PRESET_ENTRY.to_owned())?;
+				Ok(PackedBundle { info, presets: vec![preset] })
+			},
+			PackageMetaData::Bundle(bundle) => {
+				let presets = bundle
+					.presets
+					.into_iter()
+					.map(|entry| self.packed_file(entry.metadata, entry.file))
+					.collect::<Result<_, _>>()?;
+				Ok(PackedBundle { info: bundle.info, presets })
+			},
+		}
+	}
+}
+
+/// Rejects metadata that would be unsafe to use in paths, and normalizes hashes and extensions
+fn sanitize_metadata(metadata: &mut PackMetaData) -> Result<(), UnpackError> {
+	fn unsafe_name(field: NameField, value: &str) -> impl FnOnce(NameError) -> UnpackError + '_ {
+		move |reason| UnpackError::UnsafeName { field, value: value.to_owned(), reason }
+	}
+
+	sanitize::check_file_name(&metadata.name)
+		.map_err(unsafe_name(NameField::PresetName, &metadata.name))?;
+
+	for asset in &mut metadata.assets {
+		sanitize::check_file_name(&asset.name)
+			.map_err(unsafe_name(NameField::AssetName, &asset.name))?;
+		asset.hash = sanitize::normalize_hash(&asset.hash)
+			.map_err(unsafe_name(NameField::AssetHash, &asset.hash))?;
+		asset.extension = sanitize::normalize_extension(&asset.extension)
+			.map_err(unsafe_name(NameField::AssetExtension, &asset.extension))?;
+	}
+
+	Ok(())
+}
+
+/// Where `asset` is installed when named `name`, in the randomizer directory if `random`
+fn asset_path(env: &KeysightEnv, asset: &MetaEntry, name: &str, random: bool) -> PathBuf {
+	env.custom_asset_dir(random)
+		.join(asset.texture_type.path_name())
+		.join(format!("{}.{}", name, asset.extension))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+	let mut hasher = blake3::Hasher::new();
+	io::copy(&mut File::open(path)?, &mut hasher)?;
+	Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// How an asset that already exists is installed
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Resolution {
+	/// Replace the existing file
+	#[default]
+	Overwrite,
+	/// Leave the existing file as it is and use it instead of the packed asset
+	KeepExisting,
+	/// Install the asset under a free name, and point the preset at that name
+	Rename,
+	/// Keep the existing file if it is identical to the packed asset, overwrite it otherwise
+	SkipIdentical,
+}
+
+impl Resolution {
+	pub const ALL: [Resolution; 4] =
+		[Resolution::Overwrite, Resolution::KeepExisting, Resolution::Rename, Resolution::SkipIdentical];
+}
+
+impl fmt::Display for Resolution {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Resolution::Overwrite => "Overwrite",
+			Resolution::KeepExisting => "Keep existing",
+			Resolution::Rename => "Rename",
+			Resolution::SkipIdentical => "Skip if identical",
+		})
+	}
+}
+
+/// How an asset compares to the installed files of the same name
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AssetStatus {
+	/// No file of that name is installed
+	Absent,
+	/// All installed files of that name have the same contents as the asset
+	Identical,
+	/// At least one installed file of that name has different contents
+	Differing,
+}
+
+/// An installed file with the same name as an asset
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExistingFile {
+	pub path:      PathBuf,
+	/// Whether the file is in the randomizer directory
+	pub random:    bool,
+	/// Whether the file has the same hash as the asset
+	pub identical: bool,
+}
+
+/// An asset whose name is already taken by an installed file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conflict {
+	/// Index of the asset in the package metadata
+	pub asset:      usize,
+	/// Either [`AssetStatus::Identical`] or [`AssetStatus::Differing`]
+	pub status:     AssetStatus,
+	pub existing:   Vec<ExistingFile>,
+	pub resolution: Resolution,
+}
+
+impl Conflict {
+	/// Whether installing with the chosen resolution can replace a file with different contents
+	pub fn may_overwrite(&self) -> bool {
+		self.status == AssetStatus::Differing
+			&& matches!(self.resolution, Resolution::Overwrite | Resolution::SkipIdentical)
+	}
+}
+
+/// The assets of a package compared against the installed textures
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConflictReport {
+	/// Status of each asset, in the order of the package metadata
+	pub statuses:  Vec<AssetStatus>,
+	/// The assets that are not absent
+	pub conflicts: Vec<Conflict>,
+}
+
+impl ConflictReport {
+	/// Hashes the installed files sharing a name with an asset, in the regular and the
+	/// randomizer directory. Identical assets are skipped by default.
+	fn new(env: &KeysightEnv, metadata: &PackMetaData) -> Result<Self, UnpackError> {
+		let mut report = Self { statuses: Vec::new(), conflicts: Vec::new() };
+
+		for (idx, asset) in metadata.assets.iter().enumerate() {
+			let mut existing = Vec::new();
+			for random in [false, true] {
+				let path = asset_path(env, asset, &asset.name, random);
+				if !path.exists() {
+					continue;
+				}
+
+				let hash = hash_file(&path)
+					.map_err(|reason| UnpackError::ExistingIOError { path: path.clone(), reason })?;
+				existing.push(ExistingFile { path, random, identical: hash == asset.hash });
+			}
+
+			let status = if existing.is_empty() {
+				AssetStatus::Absent
+			} else if existing.iter().all(|e| e.identical) {
+				AssetStatus::Identical
+			} else {
+				AssetStatus::Differing
+			};
+			report.statuses.push(status);
+
+			if status != AssetStatus::Absent {
+				let resolution = match status {
+					AssetStatus::Identical => Resolution::SkipIdentical,
+					_ => Resolution::default(),
+				};
+				report.conflicts.push(Conflict { asset: idx, status, existing, resolution });
+			}
+		}
+
+		Ok(report)
+	}
+
+	/// Number of assets with the given status
+	pub fn count(&self, status: AssetStatus) -> usize {
+		self.statuses.iter().filter(|s| **s == status).count()
+	}
+}
+
+/// Uncompressed bytes read from a package, checked against [`UnpackLimits::max_total_size`]
+/// across all presets of a bundle. Entries shared by several presets only count once.
+#[derive(Default)]
+struct Total {
+	bytes:   u64,
+	entries: BTreeSet<String>,
+}
+
+/// Where an asset is installed
+enum Target {
+	Write(PathBuf),
+	/// An existing file is used instead of the asset
+	Keep(PathBuf),
+}
+
+/// Where the assets of a package are installed
+struct Plan {
+	targets: Vec<Target>,
+	/// Indices and new names of renamed assets
+	renames: Vec<(usize, String)>,
+}
+
+/// A loaded preset file that can be installed with [`PackedFile::unpack`]
+pub struct PackedFile {
+	env:          KeysightEnv,
+	path:         PathBuf,
+	limits:       UnpackLimits,
+	/// The archive entry of the preset
+	preset_entry: String,
+	metadata:  PackMetaData,
+	report:    ConflictReport,
+}
+
+impl PackedFile {
+	pub fn exists(&self) -> bool {
+		self.env.custom_preset_dir().join(format!("{}.json", self.metadata.name)).exists()
+	}
+
+	pub fn metadata(&self) -> &PackMetaData { &self.metadata }
+
+	/// How the assets compare to the installed textures
+	pub fn report(&self) -> &ConflictReport { &self.report }
+
+	pub fn conflicts(&self) -> &[Conflict] { &self.report.conflicts }
+
+	/// Allows choosing a resolution for each conflict
+	pub fn conflicts_mut(&mut self) -> &mut [Conflict] { &mut self.report.conflicts }
+
+	/// Uses `resolution` for all conflicts
+	pub fn resolve_all(&mut self, resolution: Resolution) {
+		for conflict in &mut self.report.conflicts {
+			conflict.resolution = resolution;
+		}
+	}
+
+	fn open(&self) -> Result<ZipArchive<File>, UnpackError> {
+		ZipArchive::new(File::open(&self.path).map_err(|reason| UnpackError::PackIOError { reason })?)
+			.map_err(|reason| UnpackError::ZipIOError { reason })
+	}
+
+	fn asset_file<'a>(
+		zipf: &'a mut ZipArchive<File>,
+		asset: &MetaEntry,
+	) -> Result<ZipFile<'a>, UnpackError> {
+		zipf.by_name(&format!("assets/{}", asset.hash)).map_err(|reason| match reason {
+			ZipError::FileNotFound => UnpackError::AssetNotFound { name: asset.hash.clone() },
+			other => UnpackError::ZipIOError { reason: other },
+		})
+	}
+
+	fn preset_file<'a>(
+		zipf: &'a mut ZipArchive<File>,
+		entry: &str,
+	) -> Result<ZipFile<'a>, UnpackError> {
+		zipf.by_name(entry).map_err(|reason| match reason {
+			ZipError::FileNotFound => UnpackError::AssetNotFound { name: entry.to_owned() },
+			other => UnpackError::ZipIOError { reason: other },
+		})
+	}
+
+	/// Copies an entry while enforcing the limits, counting it towards `total` and reporting it
+	/// as `current`
+	fn copy_entry(
+		&self,
+		src: &mut ZipFile,
+		dst: &mut impl io::Write,
+		total: &mut Total,
+		tracker: &mut Tracker,
+		current: &str,
+	) -> Result<(), UnpackError> {
+		if tracker.is_cancelled() {
+			return Err(UnpackError::Cancelled);
+		}
+
+		let compressed = src.compressed_size();
+		let name = src.name().to_owned();
+		let copied = self.limits.copy_entry(&name, compressed, src, dst)?;
+		tracker.advance(current, copied);
+		if total.entries.insert(name) {
+			total.bytes += copied;
+		}
+		self.limits.check_total(total.bytes)
+	}
+
+	/// File name of the preset, for progress reports
+	fn preset_name(&self) -> String { format!("{}.json", self.metadata.name) }
+
+	/// Uncompressed size of the preset and its assets, as recorded in the archive
+	fn size(&self, zipf: &mut ZipArchive<File>) -> Result<u64, UnpackError> {
+		let mut size = Self::preset_file(zipf, &self.preset_entry)?.size();
+		for asset in &self.metadata.assets {
+			size += Self::asset_file(zipf, asset)?.size();
+		}
+		Ok(size)
+	}
+
+	/// Checks every asset in the package against the hash recorded in the metadata, and that
+	/// the package stays within its limits
+	pub fn verify(&self) -> Result<(), UnpackError> {
+		let mut zipf = self.open()?;
+		self.verify_tracked(&mut zipf, &mut Total::default(), &mut Tracker::new(&Progress::default(), 0))
+	}
+
+	fn verify_tracked(
+		&self,
+		zipf: &mut ZipArchive<File>,
+		total: &mut Total,
+		tracker: &mut Tracker,
+	) -> Result<(), UnpackError> {
+		let mut preset = Self::preset_file(zipf, &self.preset_entry)?;
+		self.copy_entry(&mut preset, &mut io::sink(), total, tracker, &self.preset_name())?;
+		drop(preset);
+
+		for asset in &self.metadata.assets {
+			debug!(?asset.hash, "verifying asset");
+
+			let mut src = Self::asset_file(zipf, asset)?;
+			let mut hasher = blake3::Hasher::new();
+			let name = format!("{}.{}", asset.name, asset.extension);
+			self.copy_entry(&mut src, &mut hasher, total, tracker, &name)?;
+
+			let actual = hasher.finalize().to_hex();
+			if actual.as_str() != asset.hash {
+				return Err(UnpackError::HashMismatch {
+					name:     format!("{}.{}", asset.name, asset.extension),
+					expected: asset.hash.clone(),
+					actual:   actual.to_string(),
+				});
+			}
+		}
+
+		Ok(())
+	}
+
+	/// A name for `asset` that neither exists nor is used by another asset of the package
+	fn free_name(&self, asset: &MetaEntry, renames: &[(usize, String)]) -> String {
+		let dir = asset.texture_type.path_name();
+		let taken = |name: &str| {
+			[false, true].into_iter().any(|random| asset_path(&self.env, asset, name, random).exists())
+				|| self.metadata.assets.iter().any(|a| a.name == name && a.texture_type.path_name() == dir)
+				|| renames.iter().any(|(idx, renamed)| {
+					renamed == name && self.metadata.assets[*idx].texture_type.path_name() == dir
+				})
+		};
+
+		(1..).map(|n| format!("{} ({})", asset.name, n)).find(|name| !taken(name)).unwrap()
+	}
+
+	/// Applies the conflict resolutions
+	fn plan(&self) -> Plan {
+		let mut targets = Vec::with_capacity(self.metadata.assets.len());
+		let mut renames = Vec::new();
+
+		for (idx, asset) in self.metadata.assets.iter().enumerate() {
+			let conflict = self.conflicts().iter().find(|c| c.asset == idx);
+			let target = asset_path(&self.env, asset, &asset.name, asset.source_was_random);
+			let target = match conflict.map(|c| (c, c.resolution)) {
+				None | Some((_, Resolution::Overwrite)) => Target::Write(target),
+				Some((conflict, Resolution::KeepExisting)) => {
+					let existing = conflict.existing.iter().find(|e| e.random == asset.source_was_random);
+					Target::Keep(existing.unwrap_or(&conflict.existing[0]).path.clone())
+				},
+				Some((conflict, Resolution::SkipIdentical)) => {
+					match conflict.existing.iter().find(|e| e.identical) {
+						Some(identical) => Target::Keep(identical.path.clone()),
+						None => Target::Write(target),
+					}
+				},
+				Some((_, Resolution::Rename)) => {
+					let name = self.free_name(asset, &renames);
+					let target = asset_path(&self.env, asset, &name, asset.source_was_random);
+					renames.push((idx, name));
+					Target::Write(target)
+				},
+			};
+
+			targets.push(target);
+		}
+
+		Plan { targets, renames }
+	}
+
+	/// Verifies and installs the preset and all its assets, resolving conflicts as chosen. Assets
+	/// are restored under their original name, extension and texture directory. Either all files
+	/// are installed, or none of them are changed.
+	///
+	/// The installed files are recorded in the [`Registry`], the record is returned.
+	pub fn unpack(&self) -> Result<InstalledPack, UnpackError> { self.unpack_with(&Progress::default()) }
+
+	/// Installs like [`PackedFile::unpack`], reporting to `progress`
+	pub fn unpack_with(&self, progress: &Progress) -> Result<InstalledPack, UnpackError> {
+		let mut packs = install(&[self], progress)?;
+		Ok(packs.remove(0))
+	}
+
+	/// Stages the preset and its assets, returning the record of the files it installs
+	fn stage(
+		&self,
+		zipf: &mut ZipArchive<File>,
+		transaction: &mut Transaction,
+		registry: &Registry,
+		source_hash: &str,
+		total: &mut Total,
+		tracker: &mut Tracker,
+	) -> Result<InstalledPack, UnpackError> {
+		let install_error = |reason| UnpackError::InstallError { reason };
+		let Plan { targets, renames } = self.plan();
+		let preset_path = self.env.custom_preset_dir().join(format!("{}.json", self.metadata.name));
+
+		debug!(entry = %self.preset_entry, "unpacking preset");
+		{
+			let mut preset = Self::preset_file(zipf, &self.preset_entry)?;
+
+			let mut out_preset = transaction.stage(preset_path.clone()).map_err(install_error)?;
+
+			if renames.is_empty() {
+				self.copy_entry(&mut preset, &mut out_preset, total, tracker, &self.preset_name())?;
+			} else {
+				let mut buf = Vec::new();
+				self.copy_entry(&mut preset, &mut buf, total, tracker, &self.preset_name())?;
+				let mut json: serde_json::Value =
+					serde_json::from_slice(&buf).map_err(|reason| UnpackError::JsonError { reason })?;
+
+				for (idx, name) in &renames {
+					let asset = &self.metadata.assets[*idx];
+					debug!(from = %asset.name, to = %name, "renaming asset");
+					references::rename_texture(&mut json, asset.texture_type, &asset.name, name);
+				}
+
+				serde_json::to_writer(&mut out_preset, &json)
+					.map_err(|reason| UnpackError::JsonError { reason })?;
+				out_preset.flush().map_err(install_error)?;
+			}
+		}
+
+		let mut installed = Vec::new();
+		for (asset, target) in self.metadata.assets.iter().zip(targets) {
+			let name = format!("{}.{}", asset.name, asset.extension);
+			let (path, written) = match target {
+				Target::Write(path) => (path, true),
+				Target::Keep(path) => {
+					debug!(?asset.hash, path=%path.display(), "keeping existing asset");
+					(path, false)
+				},
+			};
+
+			let mut src = Self::asset_file(zipf, asset)?;
+			if written {
+				debug!(?asset.hash, "unpacking asset");
+				let mut dst = transaction.stage(path.clone()).map_err(install_error)?;
+				self.copy_entry(&mut src, &mut dst, total, tracker, &name)?;
+			} else {
+				tracker.advance(&name, src.size());
+			}
+			drop(src);
+
+			let file = InstalledFile { path: registry.relative(&path), written };
+			if !installed.contains(&file) {
+				installed.push(file);
+			}
+		}
+
+		Ok(InstalledPack {
+			metadata: self.metadata.clone(),
+			source_hash: source_hash.to_owned(),
+			installed: Utc::now(),
+			preset: registry.relative(&preset_path),
+			assets: installed,
+		})
+	}
+}
+
+/// Verifies and installs presets loaded from the same package in a single transaction, and
+/// records them in the registry
+fn install(presets: &[&PackedFile], progress: &Progress) -> Result<Vec<InstalledPack>, UnpackError> {
+	let first = match presets.first() {
+		Some(first) => first,
+		None => return Ok(Vec::new()),
+	};
+
+	// every entry is read twice, to verify and to install it
+	let mut zipf = first.open()?;
+	let mut size = 0;
+	for preset in presets {
+		size += preset.size(&mut zipf)?;
+	}
+	let mut tracker = Tracker::new(progress, 2 * size);
+
+	// the limit on the total size applies to the whole package, not to each preset
+	let mut total = Total::default();
+	for preset in presets {
+		preset.verify_tracked(&mut zipf, &mut total, &mut tracker)?;
+	}
+
+	let install_error = |reason| UnpackError::InstallError { reason };
+	let mut registry = Registry::load(&first.env)?;
+	let source_hash = hash_file(&first.path).map_err(|reason| UnpackError::PackIOError { reason })?;
+
+	let mut transaction = Transaction::new(&first.env.saved_dir()).map_err(install_error)?;
+	let mut packs = Vec::with_capacity(presets.len());
+	let mut total = Total::default();
+	for preset in presets {
+		packs.push(preset.stage(&mut zipf, &mut transaction, &registry, &source_hash, &mut total, &mut tracker)?);
+	}
+
+	// the registry is committed with the files, so no file is installed without being recorded
+	for pack in &packs {
+		registry.record(pack.clone());
+	}
+	let registry_file = transaction.stage(registry.file().to_owned()).map_err(install_error)?;
+	registry.write(registry_file)?;
+
+	transaction.commit().map_err(install_error)?;
+
+	Ok(packs)
+}
+
+/// A loaded package with one or more presets, of which any can be installed
+pub struct PackedBundle {
+	info:    BundleInfo,
+	presets: Vec<PackedFile>,
+}
+
+impl PackedBundle {
+	pub fn info(&self) -> &BundleInfo { &self.info }
+
+	pub fn presets(&self) -> &[PackedFile] { &self.presets }
+
+	/// Allows choosing conflict resolutions for each preset
+	pub fn presets_mut(&mut self) -> &mut [PackedFile] { &mut self.presets }
+
+	/// Checks the assets of every preset, see [`PackedFile::verify`]
+	pub fn verify(&self) -> Result<(), UnpackError> {
+		let mut zipf = match self.presets.first() {
+			Some(first) => first.open()?,
+			None => return Ok(()),
+		};
+		let mut total = Total::default();
+		let mut tracker = Tracker::new(&Progress::default(), 0);
+		self.presets.iter().try_for_each(|preset| preset.verify_tracked(&mut zipf, &mut total, &mut tracker))
+	}
+
+	/// Installs the presets at the indices in `selection` like [`PackedFile::unpack`]. Either
+	/// all of them are installed, or none.
+	///
+	/// # Panics
+	/// If an index is out of bounds
+	pub fn unpack(&self, selection: &[usize]) -> Result<Vec<InstalledPack>, UnpackError> {
+		self.unpack_with(selection, &Progress::default())
+	}
+
+	/// Installs like [`PackedBundle::unpack`], reporting to `progress`
+	pub fn unpack_with(&self, selection: &[usize], progress: &Progress) -> Result<Vec<InstalledPack>, UnpackError> {
+		let presets: Vec<&PackedFile> = selection.iter().map(|idx| &self.presets[*idx]).collect();
+		install(&presets, progress)
+	}
+
+	/// Installs every preset of the bundle
+	pub fn unpack_all(&self) -> Result<Vec<InstalledPack>, UnpackError> {
+		install(&self.presets.iter().collect::<Vec<_>>(), &Progress::default())
+	}
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::{fs::File, io::Read};
+
+use common::*;
+use kspacker_core::{
+	format::{self, PackageMetaData},
+	ExtraMeta,
+	PackOptions,
+	PackablePreset,
+	Packer,
+	TextureType,
+	UnpackError,
+	Unpacker,
+};
+use serde_json::json;
+
+/// Saves two presets sharing the texture `shared`, each with one texture of its own, and packs
+/// them into a bundle
+fn pack_bundle(ks: &FakeKeysight) -> std::path::PathBuf {
+	ks.add_texture(false, TextureType::Diffuse, "shared.png", &png(1));
+	ks.add_texture(false, TextureType::Normal, "first.png", &png(2));
+	ks.add_texture(false, TextureType::Normal, "second.png", &png(3));
+
+	for name in ["first", "second"] {
+		let mut preset = preset_fixture();
+		let material = &mut preset["effects"]["keypresses"]["keypressMaterial"];
+		material["diffuseUseTexture"] = json!(true);
+		material["diffuseTexture"] = json!("shared");
+		material["normalOn"] = json!(true);
+		material["normalTexture"] = json!(name);
+		ks.save_preset(name, &preset);
+	}
+
+	let presets: Vec<PackablePreset> = ["first", "second"]
+		.iter()
+		.map(|name| Packer::new(ks.env.clone(), 0, *name).collect(false).unwrap())
+		.collect();
+
+	let path = ks.pack_path("bundle");
+	PackablePreset::pack_bundle(&presets, &path, extra_meta(), &PackOptions::default()).unwrap();
+	path
+}
+
+#[test]
+fn stores_shared_assets_once() {
+	let source = FakeKeysight::new();
+	let path = pack_bundle(&source);
+
+	let zipf = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+	let assets: Vec<&str> = zipf.file_names().filter(|n| n.starts_with("assets/") && n.len() > 7).collect();
+	assert_eq!(assets.len(), 3);
+
+	let bundle = Unpacker::new(source.env.clone(), &path).load_bundle().unwrap();
+	assert_eq!(bundle.info().name, "bundle");
+	assert_eq!(bundle.presets().len(), 2);
+	bundle.verify().unwrap();
+}
+
+#[test]
+fn bundles_are_readable_by_their_writer() {
+	let source = FakeKeysight::new();
+	let path = pack_bundle(&source);
+
+	let mut zipf = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+	let mut json = Vec::new();
+	zipf.by_name(format::METADATA_ENTRY).unwrap().read_to_end(&mut json).unwrap();
+	match format::read_metadata(&json).unwrap() {
+		PackageMetaData::Bundle(bundle) => assert_eq!(bundle.requires, format::BUNDLE_MIN_READER_VERSION),
+		other => panic!("expected a bundle, got {:?}", other),
+	}
+}
+
+#[test]
+fn imports_all_presets() {
+	let source = FakeKeysight::new();
+	let path = pack_bundle(&source);
+
+	let target = FakeKeysight::new();
+	let bundle = Unpacker::new(target.env.clone(), &path).load_bundle().unwrap();
+	let packs = bundle.unpack_all().unwrap();
+
+	assert_eq!(packs.iter().map(|p| p.name()).collect::<Vec<_>>(), ["first", "second"]);
+	for name in ["first", "second"] {
+		assert!(target.env.custom_preset_dir().join(format!("{}.json", name)).exists());
+		assert!(target.env.custom_asset_dir(false).join("Normal").join(format!("{}.png", name)).exists());
+	}
+	assert!(target.env.custom_asset_dir(false).join("Colour").join("shared.png").exists());
+}
+
+#[test]
+fn imports_a_subset() {
+	let source = FakeKeysight::new();
+	let path = pack_bundle(&source);
+
+	let target = FakeKeysight::new();
+	let bundle = Unpacker::new(target.env.clone(), &path).load_bundle().unwrap();
+	bundle.unpack(&[1]).unwrap();
+
+	assert!(!target.env.custom_preset_dir().join("first.json").exists());
+	assert!(target.env.custom_preset_dir().join("second.json").exists());
+	assert!(!target.env.custom_asset_dir(false).join("Normal").join("first.png").exists());
+}
+
+#[test]
+fn bundles_are_not_loaded_as_single_presets() {
+	let source = FakeKeysight::new();
+	let path = pack_bundle(&source);
+
+	let result = Unpacker::new(source.env.clone(), &path).load();
+	assert!(matches!(result, Err(UnpackError::IsBundle { presets: 2 })));
+}
+
+#[test]
+fn packs_many_textures_deterministically() {
+	let source = FakeKeysight::new();
+	source.add_texture(false, TextureType::Diffuse, "shared.png", &png(0));
+
+	let names: Vec<String> = (1..=12).map(|idx| format!("preset{}", idx)).collect();
+	for (idx, name) in names.iter().enumerate() {
+		source.add_texture(false, TextureType::Normal, &format!("{}.png", name), &png(idx as u8 + 1));
+		// the same contents as another preset's texture under a different name
+		source.add_texture(false, TextureType::Mask, &format!("{}.png", name), &png(idx as u8 / 2 + 1));
+
+		let mut preset = preset_fixture();
+		let material = &mut preset["scene"]["backdropMaterial"];
+		material["diffuseUseTexture"] = json!(true);
+		material["diffuseTexture"] = json!("shared");
+		material["normalOn"] = json!(true);
+		material["normalTexture"] = json!(name);
+		material["maskOn"] = json!(true);
+		material["maskTexture"] = json!(name);
+		source.save_preset(name, &preset);
+	}
+
+	let presets: Vec<PackablePreset> =
+		names.iter().map(|name| Packer::new(source.env.clone(), 0, name).collect(false).unwrap()).collect();
+	let pack = |name: &str| {
+		let path = source.pack_path(name);
+		let extra_meta = ExtraMeta { rename: Some(String::from("many")), ..extra_meta() };
+		let summary = PackablePreset::pack_bundle(&presets, &path, extra_meta, &PackOptions::default()).unwrap();
+		let mut zipf = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+		let entries: Vec<String> = (0..zipf.len()).map(|idx| zipf.by_index(idx).unwrap().name().to_owned()).collect();
+		(path, summary, entries)
+	};
+
+	let (path, summary, entries) = pack("first");
+	assert_eq!(summary.assets, 13);
+	assert_eq!(entries.iter().filter(|n| n.starts_with("assets/") && n.len() > 7).count(), 13);
+	assert_eq!(pack("second").2, entries);
+
+	let target = FakeKeysight::new();
+	let bundle = Unpacker::new(target.env.clone(), &path).load_bundle().unwrap();
+	bundle.verify().unwrap();
+	bundle.unpack_all().unwrap();
+	for name in &names {
+		let mask = format!("{}.png", name);
+		assert!(target.env.custom_asset_dir(false).join("Mask").join(&mask).exists(), "{}", mask);
+	}
+}
@@ -0,0 +1,186 @@
+#![allow(dead_code)]
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	fs::{self, File},
+	io::Write,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use kspacker_core::{
+	ExtraMeta,
+	KeysightEnv,
+	MetaEntry,
+	PackError,
+	PackMetaData,
+	PackOptions,
+	PackSummary,
+	Packer,
+	TextureType,
+};
+use tempfile::TempDir;
+
+/// A keysight install and data directory inside a temporary directory
+pub struct FakeKeysight {
+	pub tmp: TempDir,
+	pub env: KeysightEnv,
+}
+
+impl FakeKeysight {
+	pub fn new() -> Self {
+		let tmp = tempfile::tempdir().unwrap();
+		let env = KeysightEnv::new(tmp.path().join("install"), tmp.path().join("data"));
+
+		fs::create_dir_all(env.root_preset_dir()).unwrap();
+		fs::create_dir_all(env.custom_preset_dir()).unwrap();
+		for random in [false, true] {
+			for typ in TextureType::ALL {
+				fs::create_dir_all(env.root_asset_dir().join(typ.path_name())).unwrap();
+				fs::create_dir_all(env.custom_asset_dir(random).join(typ.path_name())).unwrap();
+			}
+		}
+
+		Self { tmp, env }
+	}
+
+	pub fn pack_path(&self, name: &str) -> PathBuf { self.tmp.path().join(format!("{}.kspreset", name)) }
+
+	/// Saves `preset` as a custom preset named `name`
+	pub fn save_preset(&self, name: &str, preset: &serde_json::Value) {
+		let path = self.env.custom_preset_dir().join(format!("{}.json", name));
+		fs::write(path, serde_json::to_vec(preset).unwrap()).unwrap();
+	}
+
+	/// Writes a custom texture to the regular or randomizer directory
+	pub fn add_texture(&self, random: bool, typ: TextureType, file: &str, data: &[u8]) -> PathBuf {
+		let path = self.env.custom_asset_dir(random).join(typ.path_name()).join(file);
+		fs::write(&path, data).unwrap();
+		path
+	}
+
+	/// Every file below the data directory
+	pub fn data_files(&self) -> Vec<PathBuf> {
+		fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+			for entry in fs::read_dir(dir).unwrap() {
+				let path = entry.unwrap().path();
+				if path.is_dir() {
+					walk(&path, files);
+				} else {
+					files.push(path);
+				}
+			}
+		}
+
+		let mut files = Vec::new();
+		walk(self.env.data_root(), &mut files);
+		files.sort();
+		files
+	}
+}
+
+/// The metadata presets are packed with, keeping their names
+pub fn extra_meta() -> ExtraMeta {
+	ExtraMeta {
+		rename:             None,
+		author:             String::from("tester"),
+		description:        String::new(),
+		version:            1,
+		current_ks_version: 0,
+	}
+}
+
+/// Packs the saved preset `name` of `ks` with `options` to [`FakeKeysight::pack_path`]
+pub fn pack_with(
+	ks: &FakeKeysight,
+	name: &str,
+	options: &PackOptions,
+) -> Result<(PathBuf, PackSummary), PackError> {
+	let path = ks.pack_path(name);
+	let packable = Packer::new(ks.env.clone(), 0, name).collect(false)?;
+	let summary = packable.pack(&path, extra_meta(), options)?;
+	Ok((path, summary))
+}
+
+/// A preset with every field present and all textures disabled
+pub fn preset_fixture() -> serde_json::Value {
+	serde_json::from_str(include_str!("../fixtures/preset.json")).unwrap()
+}
+
+/// A small png filled with `shade`, distinct shades have distinct contents
+pub fn png(shade: u8) -> Vec<u8> {
+	let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([shade, shade, shade, 255]));
+	let mut data = Vec::new();
+	image.write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	data
+}
+
+pub fn hash(data: &[u8]) -> String { blake3::hash(data).to_hex().to_string() }
+
+pub fn meta_entry(name: &str, data: &[u8]) -> MetaEntry {
+	MetaEntry::new(hash(data), name, "png", TextureType::Diffuse, false)
+}
+
+pub fn metadata(name: &str, assets: Vec<MetaEntry>) -> PackMetaData {
+	PackMetaData::new(name, "tester", "", 1, 1, assets)
+}
+
+/// Writes a package by hand, `entries` are raw `(zip path, contents)` pairs
+pub fn craft_pack(path: &Path, metadata: &str, entries: &[(&str, &[u8])]) {
+	let mut zipf = zip::ZipWriter::new(File::create(path).unwrap());
+	let options = zip::write::FileOptions::default();
+
+	for (name, data) in entries {
+		zipf.start_file(*name, options).unwrap();
+		zipf.write_all(data).unwrap();
+	}
+
+	zipf.start_file("metadata.json", options).unwrap();
+	zipf.write_all(metadata.as_bytes()).unwrap();
+	zipf.finish().unwrap();
+}
+
+/// Writes a well formed package containing `assets` as `(name, contents)`
+pub fn write_pack(path: &Path, name: &str, assets: &[(&str, &[u8])]) -> PackMetaData {
+	let meta = metadata(name, assets.iter().map(|(name, data)| meta_entry(name, data)).collect());
+
+	let names: Vec<String> = assets.iter().map(|(_, data)| format!("assets/{}", hash(data))).collect();
+	let mut entries: Vec<(&str, &[u8])> = vec![("preset.json", b"{}")];
+	entries.extend(names.iter().map(String::as_str).zip(assets.iter().map(|(_, data)| *data)));
+
+	craft_pack(path, &serde_json::to_string(&meta).unwrap(), &entries);
+	meta
+}
+
+/// Counts allocated bytes and remembers the peak, for tests and benches that install it as
+/// their `#[global_allocator]`
+pub struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+impl PeakAlloc {
+	/// Runs `f` and returns the most bytes it had allocated at once, beyond those allocated
+	/// before
+	pub fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+		let baseline = CURRENT.load(Ordering::Relaxed);
+		PEAK.store(baseline, Ordering::Relaxed);
+		let result = f();
+		(result, PEAK.load(Ordering::Relaxed).saturating_sub(baseline))
+	}
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let ptr = System.alloc(layout);
+		if !ptr.is_null() {
+			let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+			PEAK.fetch_max(current, Ordering::Relaxed);
+		}
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout);
+		CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+	}
+}
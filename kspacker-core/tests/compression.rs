@@ -0,0 +1,92 @@
+mod common;
+
+use std::{fs::File, io::Cursor, path::PathBuf};
+
+use common::*;
+use kspacker_core::{CompressionProfile, PackOptions, TextureType, Unpacker};
+use serde_json::json;
+use zip::CompressionMethod;
+
+/// Encodes `image` as `format`
+fn encode(image: &image::RgbImage, format: image::ImageFormat) -> Vec<u8> {
+	let mut data = Vec::new();
+	image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+	data
+}
+
+/// Saves a preset using a png of noise, which does not compress any further, and a bmp of a
+/// flat colour, which compresses well, and packs it with `options`
+fn pack(ks: &FakeKeysight, options: &PackOptions) -> PathBuf {
+	let mut state = 0x2545_f491_u32;
+	let noise = image::RgbImage::from_fn(128, 128, |_, _| {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		let [r, g, b, _] = state.to_le_bytes();
+		image::Rgb([r, g, b])
+	});
+	let flat = image::RgbImage::from_pixel(64, 64, image::Rgb([40, 80, 120]));
+	ks.add_texture(false, TextureType::Diffuse, "noise.png", &encode(&noise, image::ImageFormat::Png));
+	ks.add_texture(false, TextureType::Normal, "flat.bmp", &encode(&flat, image::ImageFormat::Bmp));
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("noise");
+	material["normalOn"] = json!(true);
+	material["normalTexture"] = json!("flat");
+	ks.save_preset("mixed", &preset);
+
+	pack_with(ks, "mixed", options).unwrap().0
+}
+
+/// The compression method of the asset named `name`, and of the metadata
+fn methods(path: &PathBuf, ks: &FakeKeysight, name: &str) -> (CompressionMethod, CompressionMethod) {
+	let packed = Unpacker::new(ks.env.clone(), path).load().unwrap();
+	let entry = packed.metadata().assets.iter().find(|a| a.name == name).unwrap();
+
+	let mut zipf = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+	let asset = zipf.by_name(&format!("assets/{}", entry.hash)).unwrap().compression();
+	let metadata = zipf.by_name("metadata.json").unwrap().compression();
+	(asset, metadata)
+}
+
+/// Packs with `options` and checks the package installs
+fn round_trip(options: &PackOptions) -> (PathBuf, FakeKeysight) {
+	let source = FakeKeysight::new();
+	let path = pack(&source, options);
+
+	let target = FakeKeysight::new();
+	Unpacker::new(target.env.clone(), &path).load().unwrap().unpack().unwrap();
+	assert!(target.env.custom_asset_dir(false).join("Normal").join("flat.bmp").exists());
+	(path, source)
+}
+
+#[test]
+fn stores_incompressible_assets() {
+	let (path, ks) = round_trip(&PackOptions::default());
+	assert_eq!(methods(&path, &ks, "noise").0, CompressionMethod::Stored);
+	assert_eq!(methods(&path, &ks, "flat"), (CompressionMethod::Zstd, CompressionMethod::Zstd));
+}
+
+#[test]
+fn falls_back_to_deflate() {
+	let options = PackOptions { compatible: true, ..PackOptions::default() };
+	let (path, ks) = round_trip(&options);
+	assert_eq!(methods(&path, &ks, "noise").0, CompressionMethod::Stored);
+	assert_eq!(methods(&path, &ks, "flat"), (CompressionMethod::Deflated, CompressionMethod::Deflated));
+}
+
+#[test]
+fn compresses_with_every_profile() {
+	for compression in CompressionProfile::ALL {
+		let options = PackOptions { compression, ..PackOptions::default() };
+		let (path, ks) = round_trip(&options);
+
+		let expected = match compression {
+			CompressionProfile::StoreOnly => CompressionMethod::Stored,
+			_ => CompressionMethod::Zstd,
+		};
+		assert_eq!(methods(&path, &ks, "flat"), (expected, expected), "{}", compression);
+	}
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::*;
+use kspacker_core::{format, PackMetaData, PackOptions, TextureType, UnpackError, Unpacker};
+use serde_json::json;
+
+fn load_metadata(ks: &FakeKeysight, metadata: serde_json::Value) -> Result<PackMetaData, UnpackError> {
+	let path = ks.pack_path("format");
+	craft_pack(&path, &metadata.to_string(), &[("preset.json", b"{}")]);
+	Ok(Unpacker::new(ks.env.clone(), &path).load()?.metadata().clone())
+}
+
+#[test]
+fn upgrades_legacy_metadata() {
+	let ks = FakeKeysight::new();
+	let mut legacy = serde_json::to_value(metadata("legacy", vec![])).unwrap();
+	legacy.as_object_mut().unwrap().retain(|key, _| key != "format_version" && key != "requires");
+
+	let upgraded = load_metadata(&ks, legacy).unwrap();
+	assert_eq!(upgraded.format_version, format::FORMAT_VERSION);
+	assert_eq!(upgraded.name, "legacy");
+}
+
+#[test]
+fn reads_current_metadata_with_unknown_fields() {
+	let ks = FakeKeysight::new();
+	let expected = metadata("current", vec![]);
+	let mut current = serde_json::to_value(&expected).unwrap();
+	current["added_later"] = json!("ignored");
+
+	assert_eq!(load_metadata(&ks, current).unwrap(), expected);
+}
+
+#[test]
+fn rejects_newer_formats() {
+	let ks = FakeKeysight::new();
+	let mut newer = serde_json::to_value(metadata("newer", vec![])).unwrap();
+	newer["format_version"] = json!(format::BUNDLE_FORMAT_VERSION + 1);
+	newer["requires"] = json!("9.0.0");
+	newer["assets"] = json!({ "changed": "layout" });
+
+	match load_metadata(&ks, newer.clone()) {
+		Err(err @ UnpackError::UnsupportedFormat { .. }) => {
+			let expected = format!("package format {} requires kspacker ≥ 9.0.0", format::BUNDLE_FORMAT_VERSION + 1);
+			assert_eq!(err.to_string(), expected);
+		},
+		other => panic!("expected unsupported format, got {:?}", other),
+	}
+
+	newer.as_object_mut().unwrap().remove("requires");
+	assert!(matches!(
+		load_metadata(&ks, newer),
+		Err(UnpackError::UnsupportedFormat { requires: None, .. })
+	));
+}
+
+#[test]
+fn rejects_packages_requiring_a_newer_release() {
+	let ks = FakeKeysight::new();
+	let mut newer = serde_json::to_value(metadata("newer", vec![])).unwrap();
+	newer["requires"] = json!("99.0.0");
+	assert!(matches!(
+		load_metadata(&ks, newer),
+		Err(UnpackError::UnsupportedFormat { version: format::FORMAT_VERSION, .. })
+	));
+
+	assert!(format::is_supported(format::MIN_READER_VERSION));
+	assert!(format::is_supported(format::BUNDLE_MIN_READER_VERSION));
+	assert!(format::is_supported(format::READER_VERSION));
+	assert!(!format::is_supported("not a release"));
+}
+
+#[test]
+fn requires_the_release_adding_its_texture_types() {
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Diffuse, "key.png", &png(1));
+	ks.add_texture(false, TextureType::NoteShape, "note.png", &png(2));
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["effects"]["keypresses"]["keypressMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("key");
+	ks.save_preset("old", &preset);
+	let note_objects = &mut preset["effects"]["noteObjects"];
+	note_objects["noteObjectsEnabled"] = json!(true);
+	note_objects["noteObjectShape"] = json!("note");
+	ks.save_preset("new", &preset);
+
+	let note_shape = TextureType::NoteShape.min_reader_version();
+	for (name, requires) in [("old", format::MIN_READER_VERSION), ("new", note_shape)] {
+		let (path, _) = pack_with(&ks, name, &PackOptions::default()).unwrap();
+		let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+		assert_eq!(packed.metadata().requires, requires);
+	}
+	assert_ne!(note_shape, format::MIN_READER_VERSION);
+	for typ in TextureType::ALL {
+		assert!(format::is_supported(typ.min_reader_version()));
+	}
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::*;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use kspacker_core::{PackOptions, TextureType};
+use serde_json::json;
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Side of the textures, whose noise does not compress so their entries are as large as them
+const SIDE: u32 = 1024;
+const TEXTURES: u32 = 8;
+
+/// An rgb png of noise seeded with `seed`
+fn noise(seed: u32) -> Vec<u8> {
+	let mut state = seed.wrapping_mul(2_654_435_761) | 1;
+	let image = image::RgbImage::from_fn(SIDE, SIDE, |_, _| {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		let [r, g, b, _] = state.to_le_bytes();
+		image::Rgb([r, g, b])
+	});
+	let mut data = Vec::new();
+	image
+		.write_with_encoder(PngEncoder::new_with_quality(&mut data, CompressionType::Fast, FilterType::NoFilter))
+		.unwrap();
+	data
+}
+
+#[test]
+fn bounds_memory_of_large_textures() {
+	let ks = FakeKeysight::new();
+	let mut preset = preset_fixture();
+	let materials: Vec<&str> = kspacker_core::references::TEXTURE_FIELDS
+		.iter()
+		.filter_map(|field| field.path.strip_suffix(".diffuseTexture"))
+		.take(TEXTURES as usize)
+		.collect();
+	assert_eq!(materials.len(), TEXTURES as usize);
+
+	let mut size = 0;
+	for (idx, material) in (0..).zip(&materials) {
+		let data = noise(idx);
+		size = size.max(data.len());
+		let name = format!("noise{}", idx);
+		ks.add_texture(false, TextureType::Diffuse, &format!("{}.png", name), &data);
+
+		let material = preset.pointer_mut(&format!("/{}", material.replace('.', "/"))).unwrap();
+		material["diffuseUseTexture"] = json!(true);
+		material["diffuseTexture"] = json!(name);
+	}
+	ks.save_preset("noise", &preset);
+
+	// each of the two workers decodes one texture at a time, the compressed entries waiting to
+	// be written must not add to that
+	let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+	let ((path, summary), peak) =
+		PeakAlloc::measure(|| pool.install(|| pack_with(&ks, "noise", &PackOptions::default()).unwrap()));
+	assert_eq!(summary.assets, TEXTURES as usize);
+	assert!(path.exists());
+	assert!(peak < size * 3, "packing used {} bytes for textures of {} bytes", peak, size);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::*;
+use kspacker_core::{
+	PackError,
+	PackOptions,
+	Progress,
+	ProgressUpdate,
+	TextureType,
+	UnpackError,
+	Unpacker,
+};
+use serde_json::json;
+
+/// Saves a preset using two textures
+fn save_preset(ks: &FakeKeysight) {
+	ks.add_texture(false, TextureType::Diffuse, "paint.png", &png(1));
+	ks.add_texture(false, TextureType::Normal, "bumps.png", &png(2));
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("paint");
+	material["normalOn"] = json!(true);
+	material["normalTexture"] = json!("bumps");
+	ks.save_preset("tracked", &preset);
+}
+
+/// Checks that the updates count up to their total, and returns the reported file names
+fn check_updates(updates: &[ProgressUpdate]) -> Vec<String> {
+	assert!(updates.windows(2).all(|w| w[0].done <= w[1].done), "{:?}", updates);
+	let last = updates.last().expect("progress was reported");
+	assert_eq!(last.done, last.total);
+	assert_eq!(last.fraction(), 1.0);
+
+	let mut names: Vec<String> = updates.iter().map(|u| u.current.clone()).collect();
+	names.sort();
+	names.dedup();
+	names
+}
+
+#[test]
+fn reports_progress() {
+	let source = FakeKeysight::new();
+	save_preset(&source);
+
+	let (progress, updates) = Progress::channel();
+	let (path, _) = pack_with(&source, "tracked", &PackOptions { progress, ..PackOptions::default() }).unwrap();
+	let updates: Vec<ProgressUpdate> = updates.try_iter().collect();
+	assert_eq!(check_updates(&updates), ["bumps.png", "paint.png", "tracked.json"]);
+
+	let target = FakeKeysight::new();
+	let (progress, updates) = Progress::channel();
+	Unpacker::new(target.env.clone(), &path).load().unwrap().unpack_with(&progress).unwrap();
+	let updates: Vec<ProgressUpdate> = updates.try_iter().collect();
+	assert_eq!(check_updates(&updates), ["bumps.png", "paint.png", "tracked.json"]);
+}
+
+#[test]
+fn cancels_packing() {
+	let ks = FakeKeysight::new();
+	save_preset(&ks);
+
+	let progress = Progress::default();
+	progress.cancel();
+	let result = pack_with(&ks, "tracked", &PackOptions { progress, ..PackOptions::default() });
+	assert!(matches!(result, Err(PackError::Cancelled)), "{:?}", result);
+	assert!(!ks.pack_path("tracked").exists());
+}
+
+#[test]
+fn keeps_existing_package_when_packing_fails() {
+	let ks = FakeKeysight::new();
+	save_preset(&ks);
+	let (path, _) = pack_with(&ks, "tracked", &PackOptions::default()).unwrap();
+	let packed = std::fs::read(&path).unwrap();
+
+	let progress = Progress::default();
+	progress.cancel();
+	let result = pack_with(&ks, "tracked", &PackOptions { progress, ..PackOptions::default() });
+	assert!(matches!(result, Err(PackError::Cancelled)), "{:?}", result);
+
+	ks.add_texture(false, TextureType::Diffuse, "paint.png", b"\x89PNG\r\n\x1a\n truncated");
+	let result = pack_with(&ks, "tracked", &PackOptions::default());
+	assert!(matches!(result, Err(PackError::InvalidTexture { .. })), "{:?}", result);
+
+	assert_eq!(std::fs::read(&path).unwrap(), packed);
+	let leftover = std::fs::read_dir(path.parent().unwrap())
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name())
+		.find(|name| name.to_string_lossy().starts_with(".kspacker"));
+	assert_eq!(leftover, None, "temporary packages are removed");
+}
+
+#[test]
+fn cancels_unpacking() {
+	let source = FakeKeysight::new();
+	save_preset(&source);
+	let (path, _) = pack_with(&source, "tracked", &PackOptions::default()).unwrap();
+
+	// cancels after the first entry, while verifying
+	let target = FakeKeysight::new();
+	let cancel = std::sync::Arc::new(std::sync::OnceLock::<Progress>::new());
+	let progress = Progress::new({
+		let cancel = cancel.clone();
+		move |_| cancel.get().unwrap().cancel()
+	});
+	cancel.set(progress.clone()).unwrap();
+
+	let result = Unpacker::new(target.env.clone(), &path).load().unwrap().unpack_with(&progress);
+	assert!(matches!(result, Err(UnpackError::Cancelled)), "{:?}", result.map(drop));
+	assert!(target.data_files().is_empty());
+}
@@ -0,0 +1,251 @@
+mod common;
+
+use std::{collections::BTreeSet, fs};
+
+use common::*;
+use kspacker_core::{
+	references::{self, TEXTURE_FIELDS},
+	CollectPolicy,
+	FoundAsset,
+	KeysightPresetElement,
+	PackError,
+	Packer,
+	TextureType,
+};
+use serde_json::{json, Value};
+
+/// Keys of string fields that never name a texture
+const NOT_TEXTURES: &[&str] = &[
+	"name",
+	"author",
+	"description",
+	"mode",
+	"materialMode",
+	"damperStyle",
+	"lastDetectedRandomSeed",
+	"vectorFieldStyle",
+	"lifetimeMode",
+	"baseLocation",
+];
+
+/// Light bar layers, whose materials are found by key
+const LIGHT_LAYERS: &str = "widgets.lightBars[].lightArray[]";
+
+/// The preset fixture with one particle, pulse and light bar with one layer
+fn populated_preset() -> Value {
+	let mut preset = preset_fixture();
+	preset["effects"]["particles"]["particleV2Array"] =
+		json!([serde_json::from_str::<Value>(include_str!("fixtures/particle.json")).unwrap()]);
+	preset["effects"]["pulses"]["pulseArrayV2"] =
+		json!([serde_json::from_str::<Value>(include_str!("fixtures/pulse.json")).unwrap()]);
+	preset["widgets"]["lightBars"] =
+		json!([serde_json::from_str::<Value>(include_str!("fixtures/light_bar.json")).unwrap()]);
+	preset
+}
+
+/// The paths of all string fields in `value`, in the form of [`references::TextureField::path`]
+fn string_paths(value: &Value, path: &str, paths: &mut BTreeSet<String>) {
+	match value {
+		Value::String(_) => {
+			paths.insert(path.to_owned());
+		},
+		Value::Array(values) => values.iter().for_each(|v| string_paths(v, &format!("{}[]", path), paths)),
+		Value::Object(fields) => {
+			for (key, value) in fields {
+				let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+				string_paths(value, &path, paths);
+			}
+		},
+		_ => {},
+	}
+}
+
+#[test]
+fn covers_every_texture_field() {
+	let preset = populated_preset();
+	serde_json::from_value::<KeysightPresetElement>(preset.clone()).expect("fixtures match the preset model");
+
+	let mut paths = BTreeSet::new();
+	string_paths(&preset, "", &mut paths);
+
+	let table: BTreeSet<String> = TEXTURE_FIELDS.iter().map(|f| f.path.to_owned()).collect();
+	assert_eq!(table.len(), TEXTURE_FIELDS.len(), "fields are listed once");
+
+	let is_material_texture =
+		|key: &str| table.iter().any(|field| field.contains("Material.") && field.ends_with(&format!(".{}", key)));
+	for path in &paths {
+		let key = path.rsplit('.').next().unwrap();
+		let light_texture = path.starts_with(LIGHT_LAYERS) && is_material_texture(key);
+		assert!(
+			table.contains(path) || light_texture || NOT_TEXTURES.contains(&key),
+			"string field {} is neither a texture nor known to name something else",
+			path
+		);
+	}
+	assert!(
+		paths.iter().any(|path| path.starts_with(LIGHT_LAYERS) && path.ends_with(".diffuseTexture")),
+		"the light bar fixture has a layer with a material"
+	);
+
+	for field in TEXTURE_FIELDS {
+		assert!(paths.contains(field.path), "{} is not a string field of the preset", field.path);
+		for flag in field.enabled_by {
+			let pointer = format!("/{}", flag.replace("[]", ".0").replace('.', "/"));
+			assert!(preset.pointer(&pointer).is_some_and(Value::is_boolean), "{} is not a flag", flag);
+		}
+	}
+}
+
+#[test]
+fn references_follow_enable_flags() {
+	let mut preset = populated_preset();
+	preset["effects"]["noteObjects"]["noteObjectMaterial"]["specularTexture"] = json!("shiny");
+	preset["effects"]["noteObjects"]["noteObjectMaterial"]["specularUseTexture"] = json!(true);
+	preset["effects"]["noteObjects"]["noteObjectMaterial"]["roughnessTexture"] = json!("rough");
+	preset["effects"]["noteObjects"]["noteObjectMaterial"]["textureDisplace"] = json!("bumps");
+	preset["effects"]["noteObjects"]["noteObjectShape"] = json!("round");
+	preset["effects"]["pulses"]["pulsesEnabled"] = json!(true);
+	preset["effects"]["pulses"]["pulseArrayV2"][0]["enabled"] = json!(true);
+	preset["effects"]["pulses"]["pulseArrayV2"][0]["stencil"] = json!("ring");
+	preset["effects"]["pulses"]["pulseArrayV2"][0]["worldStencil"] = json!("floor");
+
+	let preset: KeysightPresetElement = serde_json::from_value(preset).unwrap();
+	let found: Vec<(TextureType, String, bool)> = references::texture_references(&preset)
+		.unwrap()
+		.into_iter()
+		.map(|r| (r.field.texture_type, r.name, r.enabled))
+		.collect();
+
+	assert_eq!(found, [
+		(TextureType::Roughness, String::from("rough"), false),
+		(TextureType::Specular, String::from("shiny"), true),
+		(TextureType::Displacement, String::from("bumps"), true),
+		(TextureType::NoteShape, String::from("round"), false),
+		(TextureType::Stencil, String::from("ring"), true),
+		(TextureType::WorldStencil, String::from("floor"), false),
+	]);
+}
+
+#[test]
+fn finds_textures_of_light_bar_layers() {
+	let mut preset = populated_preset();
+	preset["widgets"]["lightBarsEnabled"] = json!(true);
+	preset["widgets"]["lightBars"][0]["enabled"] = json!(true);
+	let material = &mut preset["widgets"]["lightBars"][0]["lightArray"][0]["material"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("glow");
+	material["normalTexture"] = json!("dents");
+
+	let parsed: KeysightPresetElement = serde_json::from_value(preset.clone()).unwrap();
+	let found: Vec<(TextureType, String, bool)> = references::texture_references(&parsed)
+		.unwrap()
+		.into_iter()
+		.map(|r| (r.field.texture_type, r.name, r.enabled))
+		.collect();
+	assert_eq!(found, [
+		(TextureType::Diffuse, String::from("glow"), true),
+		(TextureType::Normal, String::from("dents"), false),
+	]);
+
+	assert_eq!(references::rename_texture(&mut preset, TextureType::Emissive, "glow", "shine"), 1);
+	let material = &preset["widgets"]["lightBars"][0]["lightArray"][0]["material"];
+	assert_eq!(material["diffuseTexture"], json!("shine"));
+
+	preset["widgets"]["lightBars"][0]["enabled"] = json!(false);
+	let parsed: KeysightPresetElement = serde_json::from_value(preset).unwrap();
+	assert!(references::texture_references(&parsed).unwrap().iter().all(|r| !r.enabled));
+}
+
+#[test]
+fn collects_textures_of_every_field() {
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Specular, "shiny.png", &png(1));
+	ks.add_texture(false, TextureType::Displacement, "bumps.png", &png(2));
+	ks.add_texture(false, TextureType::NoteShape, "round.png", &png(3));
+	ks.add_texture(false, TextureType::NoteBorder, "outline.png", &png(4));
+
+	let mut preset = populated_preset();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["specularUseTexture"] = json!(true);
+	material["specularTexture"] = json!("shiny");
+	material["roughnessTexture"] = json!("shiny");
+	material["textureDisplace"] = json!("bumps");
+	let note_objects = &mut preset["effects"]["noteObjects"];
+	note_objects["noteObjectsEnabled"] = json!(true);
+	note_objects["noteObjectShape"] = json!("round");
+	note_objects["noteObjectBorder"] = json!("outline");
+	ks.save_preset("everything", &preset);
+
+	let packable = Packer::new(ks.env.clone(), 0, "everything").collect(false).unwrap();
+	let mut found: Vec<(TextureType, &str)> =
+		packable.assets().iter().map(|a| (a.texture_type, a.name.as_str())).collect();
+	found.sort_by_key(|(_, name)| *name);
+
+	assert_eq!(found, [
+		(TextureType::Displacement, "bumps"),
+		(TextureType::NoteBorder, "outline"),
+		(TextureType::NoteShape, "round"),
+		(TextureType::Specular, "shiny"),
+	]);
+}
+
+#[test]
+fn collects_disabled_textures_on_request() {
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Shape, "spark.png", &png(1));
+	ks.add_texture(false, TextureType::Normal, "bumpy.png", &png(2));
+	ks.add_texture(false, TextureType::Diffuse, "paint.png", &png(3));
+
+	let mut preset = populated_preset();
+	preset["effects"]["particles"]["particlesEnabled"] = json!(true);
+	preset["effects"]["particles"]["particleV2Array"][0]["shape"] = json!("spark");
+	let material = &mut preset["scene"]["octaveMaterial"];
+	material["normalTexture"] = json!("bumpy");
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("paint");
+	ks.save_preset("toggled", &preset);
+
+	let collect = |policy| {
+		let packable = Packer::new(ks.env.clone(), 0, "toggled").policy(policy).collect(false).unwrap();
+		let mut found: Vec<(String, bool)> =
+			packable.assets().iter().map(|a| (a.name.clone(), a.active)).collect();
+		found.sort();
+		found
+	};
+
+	assert_eq!(collect(CollectPolicy::EnabledOnly), [(String::from("paint"), true)]);
+	assert_eq!(collect(CollectPolicy::AllReferenced), [
+		(String::from("bumpy"), false),
+		(String::from("paint"), true),
+		(String::from("spark"), false),
+	]);
+}
+
+#[test]
+fn reports_missing_and_builtin_assets() {
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Diffuse, "custom.png", &png(1));
+	let builtin_dir = ks.env.root_asset_dir().join(TextureType::Normal.path_name());
+	fs::write(builtin_dir.join("stock.png"), png(2)).unwrap();
+
+	let mut preset = populated_preset();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("custom");
+	material["normalOn"] = json!(true);
+	material["normalTexture"] = json!("stock");
+	material["maskOn"] = json!(true);
+	material["maskTexture"] = json!("deleted");
+	ks.save_preset("incomplete", &preset);
+
+	let packable = Packer::new(ks.env.clone(), 0, "incomplete").collect(false).unwrap();
+	let names = |assets: &[FoundAsset]| assets.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+	assert_eq!(names(packable.assets()), ["custom"]);
+	assert_eq!(names(packable.builtin()), ["stock"]);
+	assert_eq!(names(packable.missing()), ["deleted"]);
+
+	match Packer::new(ks.env.clone(), 0, "incomplete").strict(true).collect(false) {
+		Err(PackError::MissingAssets { names }) => assert_eq!(names, ["deleted"]),
+		other => panic!("expected missing assets, got {:?}", other),
+	}
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::path::Path;
+
+use common::*;
+use kspacker_core::{Registry, RegistryError, TextureType, Unpacker};
+
+fn install(ks: &FakeKeysight, name: &str, assets: &[(&str, &[u8])]) {
+	let path = ks.pack_path(name);
+	write_pack(&path, name, assets);
+	Unpacker::new(ks.env.clone(), &path).load().unwrap().unpack().unwrap();
+}
+
+fn texture(name: &str) -> String {
+	Path::new("Textures").join(TextureType::Diffuse.path_name()).join(name).display().to_string()
+}
+
+#[test]
+fn records_installed_files() {
+	let ks = FakeKeysight::new();
+	install(&ks, "first", &[("a", b"a"), ("b", b"b")]);
+
+	let registry = Registry::load(&ks.env).unwrap();
+	assert_eq!(registry.packs().len(), 1);
+
+	let pack = registry.find("first").unwrap();
+	assert_eq!(pack.source_hash, hash(&std::fs::read(ks.pack_path("first")).unwrap()));
+	let written: Vec<_> = pack.written_files().map(|p| p.display().to_string()).collect();
+	assert_eq!(written, [
+		Path::new("Presets").join("first.json").display().to_string(),
+		texture("a.png"),
+		texture("b.png"),
+	]);
+	for path in pack.written_files() {
+		assert!(registry.resolve(path).exists());
+	}
+}
+
+#[test]
+fn keeps_shared_assets_until_last_pack_is_uninstalled() {
+	let ks = FakeKeysight::new();
+	install(&ks, "first", &[("shared", b"shared"), ("own", b"own")]);
+	// the identical asset is kept, but still used by the second pack
+	install(&ks, "second", &[("shared", b"shared")]);
+
+	let mut registry = Registry::load(&ks.env).unwrap();
+	let uninstalled = registry.uninstall("first").unwrap();
+	registry.save().unwrap();
+
+	let shared = registry.resolve(Path::new(&texture("shared.png")));
+	assert_eq!(uninstalled.shared, [Path::new(&texture("shared.png"))]);
+	assert_eq!(uninstalled.removed.len(), 2);
+	assert!(shared.exists());
+	assert!(!registry.resolve(Path::new(&texture("own.png"))).exists());
+
+	let mut registry = Registry::load(&ks.env).unwrap();
+	assert!(registry.find("first").is_none());
+	registry.uninstall("second").unwrap();
+	registry.save().unwrap();
+	assert!(!shared.exists());
+
+	assert_eq!(ks.data_files(), [ks.env.registry_file()]);
+	assert!(matches!(registry.uninstall("second"), Err(RegistryError::NotInstalled { .. })));
+}
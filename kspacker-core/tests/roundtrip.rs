@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+
+use common::*;
+use kspacker_core::{PackOptions, TextureType, Unpacker};
+use serde_json::json;
+
+/// Packs the saved preset `name` of `from` and imports it into `to`
+fn round_trip(from: &FakeKeysight, to: &FakeKeysight, name: &str) {
+	let (path, _) = pack_with(from, name, &PackOptions::default()).unwrap();
+	Unpacker::new(to.env.clone(), &path).load().unwrap().unpack().unwrap();
+}
+
+/// The data files of `ks` except the registry, relative to its data directory
+fn relative_files(ks: &FakeKeysight) -> Vec<String> {
+	ks.data_files()
+		.iter()
+		.filter(|path| **path != ks.env.registry_file())
+		.map(|path| path.strip_prefix(ks.env.data_root()).unwrap().display().to_string())
+		.collect()
+}
+
+#[test]
+fn restores_textures_to_their_original_paths() {
+	let source = FakeKeysight::new();
+	let random = source.add_texture(true, TextureType::Diffuse, "foo.png", &png(1));
+	let normal = source.add_texture(false, TextureType::Normal, "bar.png", &png(2));
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["effects"]["keypresses"]["keypressMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!("foo");
+	material["normalOn"] = json!(true);
+	material["normalTexture"] = json!("bar");
+	source.save_preset("restore", &preset);
+
+	let target = FakeKeysight::new();
+	round_trip(&source, &target, "restore");
+
+	assert_eq!(relative_files(&target), relative_files(&source));
+	for path in [random, normal] {
+		let restored = target.env.data_root().join(path.strip_prefix(source.env.data_root()).unwrap());
+		assert_eq!(fs::read(restored).unwrap(), fs::read(path).unwrap());
+	}
+}
+
+#[test]
+fn restores_textures_sharing_contents() {
+	let source = FakeKeysight::new();
+	source.add_texture(false, TextureType::Diffuse, "first.png", &png(3));
+	source.add_texture(false, TextureType::Diffuse, "second.png", &png(3));
+
+	let mut preset = preset_fixture();
+	let keypress = &mut preset["effects"]["keypresses"]["keypressMaterial"];
+	keypress["diffuseUseTexture"] = json!(true);
+	keypress["diffuseTexture"] = json!("first");
+	let note = &mut preset["effects"]["noteObjects"]["noteObjectMaterial"];
+	note["diffuseUseTexture"] = json!(true);
+	note["diffuseTexture"] = json!("second");
+	source.save_preset("shared", &preset);
+
+	let target = FakeKeysight::new();
+	round_trip(&source, &target, "shared");
+
+	assert_eq!(relative_files(&target), relative_files(&source));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::*;
+use kspacker_core::{
+	sanitize::{self, NameError, NameField},
+	UnpackError,
+	Unpacker,
+};
+
+/// Loads a crafted package and returns the rejected field and value
+fn load_rejected(ks: &FakeKeysight, meta: &kspacker_core::PackMetaData) -> (NameField, String, NameError) {
+	let path = ks.pack_path("crafted");
+	craft_pack(&path, &serde_json::to_string(meta).unwrap(), &[("preset.json", b"{}")]);
+
+	match Unpacker::new(ks.env.clone(), &path).load() {
+		Err(UnpackError::UnsafeName { field, value, reason }) => (field, value, reason),
+		Err(other) => panic!("expected unsafe name, got {:?}", other),
+		Ok(_) => panic!("crafted package was accepted"),
+	}
+}
+
+#[test]
+fn rejects_traversal_in_preset_name() {
+	let ks = FakeKeysight::new();
+
+	for name in ["../../evil", "..", "/etc/passwd", r"C:\Windows\evil", r"..\evil"] {
+		let (field, value, _) = load_rejected(&ks, &metadata(name, vec![]));
+		assert_eq!(field, NameField::PresetName);
+		assert_eq!(value, name);
+	}
+
+	assert!(ks.data_files().is_empty());
+}
+
+#[test]
+fn rejects_traversal_in_asset_fields() {
+	let ks = FakeKeysight::new();
+
+	let mut entry = meta_entry("../../../evil", b"data");
+	let (field, ..) = load_rejected(&ks, &metadata("preset", vec![entry.clone()]));
+	assert_eq!(field, NameField::AssetName);
+
+	entry.name = String::from("fine");
+	entry.hash = format!("../{}", &entry.hash[3..]);
+	let (field, _, reason) = load_rejected(&ks, &metadata("preset", vec![entry.clone()]));
+	assert_eq!((field, reason), (NameField::AssetHash, NameError::NotAHash));
+
+	let mut entry = meta_entry("fine", b"data");
+	entry.extension = String::from("/x");
+	let (field, _, reason) = load_rejected(&ks, &metadata("preset", vec![entry]));
+	assert_eq!((field, reason), (NameField::AssetExtension, NameError::InvalidChar('/')));
+
+	assert!(ks.data_files().is_empty());
+}
+
+#[test]
+fn normalizes_hash_and_extension() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("upper");
+
+	let mut entry = meta_entry("texture", b"data");
+	entry.hash = entry.hash.to_ascii_uppercase();
+	entry.extension = String::from("PNG");
+	craft_pack(&path, &serde_json::to_string(&metadata("upper", vec![entry])).unwrap(), &[
+		("preset.json", b"{}"),
+		(&format!("assets/{}", hash(b"data")), b"data"),
+	]);
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	assert_eq!(packed.metadata().assets[0].hash, hash(b"data"));
+	assert_eq!(packed.metadata().assets[0].extension, "png");
+	packed.unpack().unwrap();
+}
+
+#[test]
+fn checks_file_names() {
+	assert_eq!(sanitize::check_file_name("Plain (default)"), Ok(()));
+	assert_eq!(sanitize::check_file_name("näme with ünicode"), Ok(()));
+	assert_eq!(sanitize::check_file_name("a..b"), Ok(()));
+
+	assert_eq!(sanitize::check_file_name(""), Err(NameError::Empty));
+	assert_eq!(sanitize::check_file_name("."), Err(NameError::Relative));
+	assert_eq!(sanitize::check_file_name("a/b"), Err(NameError::InvalidChar('/')));
+	assert_eq!(sanitize::check_file_name("a\\b"), Err(NameError::InvalidChar('\\')));
+	assert_eq!(sanitize::check_file_name("C:"), Err(NameError::InvalidChar(':')));
+	assert_eq!(sanitize::check_file_name("a\0b"), Err(NameError::ControlChar));
+	assert_eq!(sanitize::check_file_name("con"), Err(NameError::ReservedName));
+	assert_eq!(sanitize::check_file_name("LPT1.tar.gz"), Err(NameError::ReservedName));
+	assert_eq!(sanitize::check_file_name("name."), Err(NameError::TrailingDot));
+	assert_eq!(sanitize::check_file_name("name "), Err(NameError::TrailingDot));
+	assert_eq!(
+		sanitize::check_file_name(&"a".repeat(sanitize::MAX_NAME_LEN + 1)),
+		Err(NameError::TooLong { max: sanitize::MAX_NAME_LEN })
+	);
+}
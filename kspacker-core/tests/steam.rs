@@ -0,0 +1,144 @@
+use std::{fs, path::Path};
+
+use kspacker_core::{
+	steam::{self, Vdf},
+	KeysightEnv,
+};
+
+fn write(path: &Path, contents: &str) {
+	fs::create_dir_all(path.parent().unwrap()).unwrap();
+	fs::write(path, contents).unwrap();
+}
+
+fn library_folders_vdf(libraries: &[&Path]) -> String {
+	let mut vdf = String::from("\"libraryfolders\"\n{\n");
+	for (idx, library) in libraries.iter().enumerate() {
+		vdf.push_str(&format!(
+			"\t\"{}\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"label\"\t\t\"\"\n\t\t\"apps\"\n\t\t{{\n\t\t}}\n\t}}\n",
+			idx,
+			library.display().to_string().replace('\\', "\\\\")
+		));
+	}
+	vdf.push_str("}\n");
+	vdf
+}
+
+fn install_keysight(library: &Path, install_dir: &str) {
+	write(
+		&library.join("steamapps").join(format!("appmanifest_{}.acf", steam::KEYSIGHT_APP_ID)),
+		&format!(
+			"\"AppState\"\n{{\n\t\"appid\"\t\t\"{}\"\n\t\"installdir\"\t\t\"{}\"\n}}\n",
+			steam::KEYSIGHT_APP_ID,
+			install_dir
+		),
+	);
+	fs::create_dir_all(library.join("steamapps").join("common").join(install_dir).join("Keysight"))
+		.unwrap();
+	fs::create_dir_all(steam::proton_data_dir(library)).unwrap();
+}
+
+#[test]
+fn parses_vdf() {
+	let vdf = Vdf::parse(
+		r#"
+		// comment
+		"LibraryFolders"
+		{
+			"TimeNextStatsReport"	"1657000000"
+			"1"		"D:\\SteamLibrary"
+			"2"
+			{
+				"path"	"E:\\Games \"Steam\""
+			}
+		}
+		"#,
+	)
+	.unwrap();
+
+	let folders = vdf.get("libraryfolders").unwrap();
+	assert_eq!(folders.get("1").and_then(Vdf::as_str), Some(r"D:\SteamLibrary"));
+	assert_eq!(
+		folders.get("2").and_then(|v| v.get("PATH")).and_then(Vdf::as_str),
+		Some(r#"E:\Games "Steam""#)
+	);
+}
+
+#[test]
+fn rejects_unbalanced_vdf() {
+	assert_eq!(Vdf::parse("\"a\" { \"b\" \"c\""), None);
+	assert_eq!(Vdf::parse("\"a\" }"), None);
+}
+
+#[test]
+fn discovers_proton_in_secondary_library() {
+	let tmp = tempfile::tempdir().unwrap();
+	let steam_root = tmp.path().join("Steam");
+	let library = tmp.path().join("Library");
+
+	write(
+		&steam_root.join("steamapps").join("libraryfolders.vdf"),
+		&library_folders_vdf(&[&steam_root, &library]),
+	);
+	install_keysight(&library, "Keysight");
+
+	let found = steam::discover_proton(&[steam_root]);
+	let library = library.canonicalize().unwrap();
+	assert_eq!(found, vec![KeysightEnv::new(
+		library.join("steamapps").join("common").join("Keysight"),
+		steam::proton_data_dir(&library)
+	)]);
+}
+
+#[test]
+fn discovers_custom_install_dir_once() {
+	let tmp = tempfile::tempdir().unwrap();
+	let steam_root = tmp.path().join("Steam");
+
+	write(
+		&steam_root.join("steamapps").join("libraryfolders.vdf"),
+		&library_folders_vdf(&[&steam_root]),
+	);
+	install_keysight(&steam_root, "Keysight Renamed");
+
+	// passing the same client twice must not produce duplicates
+	let found = steam::discover_proton(&[steam_root.clone(), steam_root.clone()]);
+	assert_eq!(found.len(), 1);
+	assert!(found[0].install_root().ends_with("Keysight Renamed"));
+}
+
+#[test]
+fn ignores_library_without_prefix() {
+	let tmp = tempfile::tempdir().unwrap();
+	let steam_root = tmp.path().join("Steam");
+
+	install_keysight(&steam_root, "Keysight");
+	fs::remove_dir_all(steam_root.join("steamapps").join("compatdata")).unwrap();
+
+	assert!(steam::discover_proton(&[steam_root]).is_empty());
+}
+
+#[test]
+fn detects_only_valid_installations() {
+	let tmp = tempfile::tempdir().unwrap();
+	let steam_root = tmp.path().join("Steam");
+	let valid = tmp.path().join("Valid");
+	let broken = tmp.path().join("Broken");
+
+	write(
+		&steam_root.join("steamapps").join("libraryfolders.vdf"),
+		&library_folders_vdf(&[&steam_root, &valid, &broken]),
+	);
+	install_keysight(&valid, "Keysight");
+	install_keysight(&broken, "Keysight");
+
+	let valid_root = valid.canonicalize().unwrap().join("steamapps").join("common").join("Keysight");
+	write(
+		&kspacker_core::helpers::default_preset_file(&valid_root),
+		r#"{"versionForUpdatePurposes": 42}"#,
+	);
+
+	assert_eq!(steam::detect_installations(&[steam_root]), vec![steam::Installation {
+		install_root: valid_root,
+		version:      42,
+	}]);
+}
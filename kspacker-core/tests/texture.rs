@@ -0,0 +1,311 @@
+mod common;
+
+use std::io::Read;
+
+use common::*;
+use image::codecs::{
+	jpeg::JpegEncoder,
+	png::{CompressionType, FilterType, PngEncoder},
+};
+use kspacker_core::{
+	texture::Optimise,
+	ImageFormat,
+	ImageInfo,
+	PackError,
+	PackOptions,
+	PackWarning,
+	Packer,
+	TextureType,
+	TextureWarning,
+	Unpacker,
+};
+use serde_json::json;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR";
+const WEBP: &[u8] = b"RIFF\x10\0\0\0WEBPVP8 ";
+/// An uncompressed 24 bit true colour tga header
+const TGA: &[u8] = &[0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 24, 0];
+
+#[test]
+fn detects_formats_by_contents() {
+	let cases: &[(&[u8], &str, ImageFormat)] = &[
+		(PNG, "png", ImageFormat::Png),
+		(PNG, "jpg", ImageFormat::Png),
+		(&[0xFF, 0xD8, 0xFF, 0xE0], "jpeg", ImageFormat::Jpeg),
+		(&[0x76, 0x2F, 0x31, 0x01, 2, 0, 0, 0], "exr", ImageFormat::Exr),
+		(WEBP, "webp", ImageFormat::WebP),
+		(b"BM\x36\0\0\0\0\0\0\0\x36\0\0\0", "bmp", ImageFormat::Bmp),
+		(TGA, "tga", ImageFormat::Tga),
+		(TGA, "png", ImageFormat::Unknown),
+		(b"not an image at all", "tga", ImageFormat::Unknown),
+	];
+
+	for (header, extension, expected) in cases {
+		assert_eq!(ImageFormat::detect(header, extension), *expected, "{:?} as .{}", header, extension);
+	}
+
+	assert!(ImageFormat::Jpeg.matches_extension("JPG"));
+	assert!(!ImageFormat::Png.matches_extension("jpg"));
+}
+
+/// Saves a preset using the diffuse texture `name`
+fn save_preset(ks: &FakeKeysight, name: &str) {
+	let mut preset = preset_fixture();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseUseTexture"] = json!(true);
+	material["diffuseTexture"] = json!(name);
+	ks.save_preset(name, &preset);
+}
+
+#[test]
+fn probes_extensions_in_order() {
+	let ks = FakeKeysight::new();
+	let webp = ks.add_texture(false, TextureType::Diffuse, "sheet.webp", WEBP);
+	let tga = ks.add_texture(false, TextureType::Diffuse, "sheet.tga", TGA);
+	save_preset(&ks, "sheet");
+
+	let packable = Packer::new(ks.env.clone(), 0, "sheet").collect(false).unwrap();
+	let asset = &packable.assets()[0];
+	assert_eq!((asset.ext.as_str(), &asset.path), ("tga", &tga));
+	assert_eq!(asset.candidates, [webp.as_path()]);
+
+	let packable = Packer::new(ks.env.clone(), 0, "sheet").extensions(["webp"]).collect(false).unwrap();
+	let asset = &packable.assets()[0];
+	assert_eq!((asset.ext.as_str(), &asset.path), ("webp", &webp));
+	assert!(asset.candidates.is_empty());
+}
+
+/// The contents of the asset entry `hash` in the package at `path`
+fn stored_asset(path: &std::path::Path, hash: &str) -> Vec<u8> {
+	let mut zipf = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+	let mut data = Vec::new();
+	zipf.by_name(&format!("assets/{}", hash)).unwrap().read_to_end(&mut data).unwrap();
+	data
+}
+
+fn optimise(max_size: Option<u32>) -> PackOptions {
+	PackOptions { optimise: Some(Optimise { max_size }), ..PackOptions::default() }
+}
+
+/// A gradient, which compresses noticeably better with filtering
+fn gradient(width: u32, height: u32) -> image::RgbImage {
+	image::RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, (x + y) as u8]))
+}
+
+#[test]
+fn records_detected_format() {
+	let ks = FakeKeysight::new();
+	// a png saved with the wrong extension
+	let texture = ks.add_texture(false, TextureType::Diffuse, "renamed.jpg", &png(1));
+	save_preset(&ks, "renamed");
+
+	let mismatched = TextureWarning::MismatchedExtension(ImageFormat::Png);
+	let packable = Packer::new(ks.env.clone(), 0, "renamed").collect(false).unwrap();
+	assert_eq!(packable.assets()[0].warnings, [mismatched]);
+
+	let (path, summary) = pack_with(&ks, "renamed", &PackOptions::default()).unwrap();
+	assert_eq!(summary.warnings, [PackWarning { path: texture, warning: mismatched }]);
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let entry = &packed.metadata().assets[0];
+	assert_eq!((entry.extension.as_str(), entry.format), ("jpg", ImageFormat::Png));
+}
+
+#[test]
+fn records_image_properties() {
+	let ks = FakeKeysight::new();
+	let mut data = Vec::new();
+	let image = image::GrayImage::from_pixel(6, 4, image::Luma([128]));
+	image.write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	ks.add_texture(false, TextureType::Diffuse, "odd.png", &data);
+	save_preset(&ks, "odd");
+
+	let (path, _) = pack_with(&ks, "odd", &PackOptions::default()).unwrap();
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let info = packed.metadata().assets[0].image.unwrap();
+	assert_eq!(info, ImageInfo { width: 6, height: 4, channels: 1, bit_depth: 8 });
+	assert!(!info.is_power_of_two());
+	assert!(!info.is_oversized());
+	assert_eq!(packed.metadata().assets[0].original_hash, None);
+}
+
+#[test]
+fn reports_texture_warnings() {
+	let ks = FakeKeysight::new();
+	let mut data = Vec::new();
+	let image = image::GrayImage::from_pixel(4100, 6, image::Luma([128]));
+	image.write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	let path = ks.add_texture(false, TextureType::Diffuse, "wide.png", &data);
+	save_preset(&ks, "wide");
+
+	let info = ImageInfo { width: 4100, height: 6, channels: 1, bit_depth: 8 };
+	let expected = [TextureWarning::NotPowerOfTwo(info), TextureWarning::Oversized(info)];
+	let packable = Packer::new(ks.env.clone(), 0, "wide").collect(false).unwrap();
+	assert_eq!(packable.assets()[0].warnings, expected);
+
+	let (_, summary) = pack_with(&ks, "wide", &PackOptions::default()).unwrap();
+	let warnings: Vec<_> = summary.warnings.iter().map(|w| (w.path.clone(), w.warning)).collect();
+	assert_eq!(warnings, expected.map(|warning| (path.clone(), warning)));
+
+	// scaling the texture down leaves it only not a power of two
+	let (_, summary) = pack_with(&ks, "wide", &optimise(Some(4096))).unwrap();
+	assert!(matches!(
+		summary.warnings.as_slice(),
+		[PackWarning { warning: TextureWarning::NotPowerOfTwo(_), .. }]
+	));
+}
+
+#[test]
+fn rejects_undecodable_textures() {
+	let ks = FakeKeysight::new();
+	let data = png(1);
+	ks.add_texture(false, TextureType::Diffuse, "truncated.png", &data[..data.len() / 2]);
+	save_preset(&ks, "truncated");
+	ks.add_texture(false, TextureType::Diffuse, "text.png", b"not an image");
+	save_preset(&ks, "text");
+
+	for name in ["truncated", "text"] {
+		match pack_with(&ks, name, &PackOptions::default()) {
+			Err(PackError::InvalidTexture { path, .. }) => {
+				assert_eq!(path.file_name().unwrap(), format!("{}.png", name).as_str())
+			},
+			other => panic!("expected an invalid texture, got {:?}", other),
+		}
+	}
+}
+
+#[test]
+fn rejects_truncated_large_textures() {
+	// 4100 * 4100 rgba pixels take more than MAX_DECODE_BYTES
+	let image = image::RgbaImage::from_pixel(4100, 4100, image::Rgba([20, 40, 60, 255]));
+	let mut data = Vec::new();
+	image
+		.write_with_encoder(PngEncoder::new_with_quality(&mut data, CompressionType::Fast, FilterType::NoFilter))
+		.unwrap();
+	const { assert!(4100 * 4100 * 4 > kspacker_core::texture::MAX_DECODE_BYTES) };
+
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Diffuse, "large.png", &data[..data.len() * 3 / 4]);
+	save_preset(&ks, "large");
+	match pack_with(&ks, "large", &PackOptions::default()) {
+		Err(PackError::InvalidTexture { path, .. }) => assert_eq!(path.file_name().unwrap(), "large.png"),
+		other => panic!("expected an invalid texture, got {:?}", other),
+	}
+
+	ks.add_texture(false, TextureType::Diffuse, "large.png", &data);
+	pack_with(&ks, "large", &PackOptions::default()).unwrap();
+}
+
+#[test]
+fn validates_large_textures_without_decoding() {
+	// a run length encoded 8192 * 4096 tga, every packet repeats one pixel 128 times. The
+	// pixels are noise so the package does not look like a compression bomb.
+	let (width, height) = (8192u16, 4096u16);
+	let mut data = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+	data.extend(width.to_le_bytes());
+	data.extend(height.to_le_bytes());
+	data.extend([24, 0]);
+	let mut noise = 1u32;
+	for _ in 0..usize::from(width) * usize::from(height) / 128 {
+		noise = noise.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+		let [r, g, b, _] = noise.to_be_bytes();
+		data.extend([0xFF, r, g, b]);
+	}
+	const { assert!(8192 * 4096 * 3 > kspacker_core::texture::MAX_DECODE_BYTES) };
+
+	let ks = FakeKeysight::new();
+	ks.add_texture(false, TextureType::Diffuse, "large.tga", &data);
+	save_preset(&ks, "large");
+	let (path, _) = pack_with(&ks, "large", &PackOptions::default()).unwrap();
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let image = packed.metadata().assets[0].image.unwrap();
+	assert_eq!((image.width, image.height), (8192, 4096));
+
+	ks.add_texture(false, TextureType::Diffuse, "large.tga", &data[..data.len() / 2]);
+	assert!(matches!(pack_with(&ks, "large", &PackOptions::default()), Err(PackError::InvalidTexture { .. })));
+
+	// the header of an uncompressed 8192 * 4096 bmp, without its pixels
+	let mut bmp = b"BM".to_vec();
+	bmp.extend(54u32.to_le_bytes().iter().chain(&[0; 4]).chain(&54u32.to_le_bytes()));
+	bmp.extend(40u32.to_le_bytes().iter().chain(&8192u32.to_le_bytes()).chain(&4096u32.to_le_bytes()));
+	bmp.extend(1u16.to_le_bytes().iter().chain(&24u16.to_le_bytes()).chain(&[0; 24]));
+	bmp.extend([0; 4096]);
+
+	ks.add_texture(false, TextureType::Diffuse, "wide.bmp", &bmp);
+	save_preset(&ks, "wide");
+	match pack_with(&ks, "wide", &PackOptions::default()) {
+		Err(PackError::InvalidTexture { path, .. }) => assert_eq!(path.file_name().unwrap(), "wide.bmp"),
+		other => panic!("expected an invalid texture, got {:?}", other),
+	}
+}
+
+#[test]
+fn optimises_png_losslessly() {
+	let ks = FakeKeysight::new();
+	let image = gradient(64, 64);
+	let mut data = Vec::new();
+	let encoder = PngEncoder::new_with_quality(&mut data, CompressionType::Fast, FilterType::NoFilter);
+	image.write_with_encoder(encoder).unwrap();
+	ks.add_texture(false, TextureType::Diffuse, "gradient.png", &data);
+	save_preset(&ks, "gradient");
+
+	let (path, summary) = pack_with(&ks, "gradient", &optimise(None)).unwrap();
+	assert_eq!(summary.original_size, data.len() as u64);
+	assert_eq!(summary.optimised, 1);
+	assert!(summary.saved() > 0, "{:?}", summary);
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let entry = &packed.metadata().assets[0];
+	assert_eq!(entry.original_hash.as_deref(), Some(blake3::hash(&data).to_hex().as_str()));
+	assert_ne!(entry.original_hash.as_deref(), Some(entry.hash.as_str()));
+
+	let stored = stored_asset(&path, &entry.hash);
+	assert_eq!(stored.len() as u64, summary.packed_size);
+	assert_eq!(image::load_from_memory(&stored).unwrap().to_rgb8(), image);
+	packed.verify().unwrap();
+
+	// an optimised texture cannot be optimised again
+	ks.add_texture(false, TextureType::Diffuse, "gradient.png", &stored);
+	let (_, summary) = pack_with(&ks, "gradient", &optimise(None)).unwrap();
+	assert_eq!((summary.assets, summary.optimised, summary.saved()), (1, 0, 0));
+}
+
+#[test]
+fn downscales_to_max_size() {
+	let ks = FakeKeysight::new();
+	let mut data = Vec::new();
+	gradient(64, 32).write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+	ks.add_texture(false, TextureType::Diffuse, "wide.png", &data);
+	save_preset(&ks, "wide");
+
+	let (path, _) = pack_with(&ks, "wide", &optimise(Some(16))).unwrap();
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let entry = &packed.metadata().assets[0];
+	assert_eq!(entry.image.map(|i| (i.width, i.height)), Some((16, 8)));
+
+	let stored = image::load_from_memory(&stored_asset(&path, &entry.hash)).unwrap();
+	assert_eq!((stored.width(), stored.height()), (16, 8));
+}
+
+#[test]
+fn strips_jpeg_metadata() {
+	let ks = FakeKeysight::new();
+	let mut encoded = Vec::new();
+	gradient(16, 16).write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, 80)).unwrap();
+
+	// an exif segment right after the start of image marker
+	let exif = b"Exif\0\0some camera";
+	let mut data = encoded[..2].to_vec();
+	data.extend_from_slice(&[0xFF, 0xE1]);
+	data.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+	data.extend_from_slice(exif);
+	data.extend_from_slice(&encoded[2..]);
+	ks.add_texture(false, TextureType::Diffuse, "photo.jpg", &data);
+	save_preset(&ks, "photo");
+
+	let (path, summary) = pack_with(&ks, "photo", &optimise(None)).unwrap();
+	assert_eq!(summary.saved(), exif.len() as u64 + 4);
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let stored = stored_asset(&path, &packed.metadata().assets[0].hash);
+	assert_eq!(stored, encoded);
+}
@@ -0,0 +1,321 @@
+mod common;
+
+use std::fs;
+
+use chrono::Utc;
+use common::*;
+use kspacker_core::{
+	format,
+	limits::Limit,
+	AssetStatus,
+	BundleEntry,
+	BundleInfo,
+	BundleMetaData,
+	Resolution,
+	TextureType,
+	UnpackError,
+	UnpackLimits,
+	Unpacker,
+};
+
+#[test]
+fn verifies_and_unpacks_intact_pack() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("intact");
+	write_pack(&path, "intact", &[("a", b"first texture"), ("b", b"second texture")]);
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	packed.verify().unwrap();
+	packed.unpack().unwrap();
+
+	assert!(ks.env.custom_preset_dir().join("intact.json").exists());
+}
+
+#[test]
+fn rejects_tampered_asset_before_writing() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("tampered");
+
+	let meta = metadata("tampered", vec![meta_entry("a", b"first texture"), meta_entry("b", b"original")]);
+	craft_pack(&path, &serde_json::to_string(&meta).unwrap(), &[
+		("preset.json", b"{}"),
+		(&format!("assets/{}", hash(b"first texture")), b"first texture"),
+		(&format!("assets/{}", hash(b"original")), b"modified"),
+	]);
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	match packed.verify() {
+		Err(UnpackError::HashMismatch { name, expected, actual }) => {
+			assert_eq!(name, "b.png");
+			assert_eq!(expected, hash(b"original"));
+			assert_eq!(actual, hash(b"modified"));
+		},
+		other => panic!("expected hash mismatch, got {:?}", other),
+	}
+
+	assert!(matches!(packed.unpack(), Err(UnpackError::HashMismatch { .. })));
+	assert!(ks.data_files().is_empty());
+}
+
+fn load_limited(ks: &FakeKeysight, name: &str, limits: UnpackLimits) -> Result<(), UnpackError> {
+	Unpacker::new(ks.env.clone(), ks.pack_path(name)).limits(limits).load()?.unpack().map(drop)
+}
+
+fn assert_limit(result: Result<(), UnpackError>, expected: Limit) {
+	match result {
+		Err(UnpackError::LimitExceeded { limit, .. }) => assert_eq!(limit, expected),
+		other => panic!("expected {} limit, got {:?}", expected, other),
+	}
+}
+
+#[test]
+fn enforces_size_and_count_limits() {
+	let ks = FakeKeysight::new();
+	write_pack(&ks.pack_path("limits"), "limits", &[("a", &[1; 1000]), ("b", &[2; 2000])]);
+
+	assert_limit(
+		load_limited(&ks, "limits", UnpackLimits { max_entries: 3, ..Default::default() }),
+		Limit::Entries,
+	);
+	assert_limit(
+		load_limited(&ks, "limits", UnpackLimits { max_asset_size: 1500, ..Default::default() }),
+		Limit::AssetSize,
+	);
+	assert_limit(
+		load_limited(&ks, "limits", UnpackLimits { max_total_size: 2500, ..Default::default() }),
+		Limit::TotalSize,
+	);
+	assert_limit(
+		load_limited(&ks, "limits", UnpackLimits { max_json_size: 100, ..Default::default() }),
+		Limit::JsonSize,
+	);
+
+	assert!(ks.data_files().is_empty());
+	load_limited(&ks, "limits", UnpackLimits::default()).unwrap();
+}
+
+#[test]
+fn rejects_highly_compressed_entries() {
+	let ks = FakeKeysight::new();
+	let bomb = vec![0u8; 8 * 1024 * 1024];
+	write_pack(&ks.pack_path("bomb"), "bomb", &[("bomb", &bomb)]);
+
+	assert_limit(load_limited(&ks, "bomb", UnpackLimits::default()), Limit::CompressionRatio);
+	assert!(ks.data_files().is_empty());
+
+	let trusting = UnpackLimits { max_compression_ratio: u64::MAX, ..Default::default() };
+	load_limited(&ks, "bomb", trusting).unwrap();
+}
+
+#[test]
+fn accepts_unbounded_limits() {
+	let ks = FakeKeysight::new();
+	let data = [3; 1000];
+	write_pack(&ks.pack_path("unbounded"), "unbounded", &[("a", &data)]);
+
+	let unbounded = UnpackLimits {
+		max_total_size:        u64::MAX,
+		max_asset_size:        u64::MAX,
+		max_entries:           usize::MAX,
+		max_compression_ratio: u64::MAX,
+		max_json_size:         u64::MAX,
+	};
+	load_limited(&ks, "unbounded", unbounded).unwrap();
+	assert_eq!(fs::read(ks.env.custom_asset_dir(false).join("Colour").join("a.png")).unwrap(), data);
+}
+
+/// Declares every entry of the archive at `path` one byte large in its central directory
+fn understate_sizes(path: &std::path::Path) {
+	const CENTRAL_HEADER: &[u8] = b"PK\x01\x02";
+	const SIZE_OFFSET: usize = 24;
+
+	let mut data = fs::read(path).unwrap();
+	let headers: Vec<usize> =
+		data.windows(4).enumerate().filter(|(_, w)| *w == CENTRAL_HEADER).map(|(pos, _)| pos).collect();
+	for pos in headers {
+		data[pos + SIZE_OFFSET..pos + SIZE_OFFSET + 4].copy_from_slice(&1u32.to_le_bytes());
+	}
+	fs::write(path, data).unwrap();
+}
+
+#[test]
+fn limits_the_total_size_of_bundles() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("bundle");
+	let (first, second) = ([1u8; 2000], [2u8; 2000]);
+
+	let entry = |idx: usize, name: &str, data: &[u8]| BundleEntry {
+		file:     format::bundle_preset_entry(idx),
+		metadata: metadata(name, vec![meta_entry(name, data)]),
+	};
+	let bundle = BundleMetaData {
+		format_version: format::BUNDLE_FORMAT_VERSION,
+		requires:       String::from(format::BUNDLE_MIN_READER_VERSION),
+		info:           BundleInfo {
+			name:        String::from("bundle"),
+			author:      String::new(),
+			description: String::new(),
+			packed:      Utc::now(),
+		},
+		target_version: 1,
+		presets:        vec![entry(0, "first", &first), entry(1, "second", &second)],
+	};
+	craft_pack(&path, &serde_json::to_string(&bundle).unwrap(), &[
+		("presets/0.json", b"{}"),
+		("presets/1.json", b"{}"),
+		(&format!("assets/{}", hash(&first)), &first),
+		(&format!("assets/{}", hash(&second)), &second),
+	]);
+	// only the bytes actually read can reveal the size of the whole bundle
+	understate_sizes(&path);
+
+	let limits = UnpackLimits { max_total_size: 3000, ..Default::default() };
+	let bundle = Unpacker::new(ks.env.clone(), &path).limits(limits).load_bundle().unwrap();
+	assert_limit(bundle.verify(), Limit::TotalSize);
+	assert_limit(bundle.unpack_all().map(drop), Limit::TotalSize);
+	assert!(ks.data_files().is_empty());
+
+	let bundle = Unpacker::new(ks.env.clone(), &path).load_bundle().unwrap();
+	bundle.unpack_all().unwrap();
+}
+
+#[test]
+fn rolls_back_failed_install() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("rollback");
+
+	let first = meta_entry("first", b"new first");
+	let mut second = meta_entry("second", b"new second");
+	second.texture_type = TextureType::Mask;
+	craft_pack(&path, &serde_json::to_string(&metadata("rollback", vec![first.clone(), second])).unwrap(), &[
+		("preset.json", b"new preset"),
+		(&format!("assets/{}", hash(b"new first")), b"new first"),
+		(&format!("assets/{}", hash(b"new second")), b"new second"),
+	]);
+
+	let preset = ks.env.custom_preset_dir().join("rollback.json");
+	let first_target = ks
+		.env
+		.custom_asset_dir(false)
+		.join(TextureType::Diffuse.path_name())
+		.join("first.png");
+	fs::write(&preset, "old preset").unwrap();
+	fs::write(&first_target, "old first").unwrap();
+
+	let earlier = ks.pack_path("earlier");
+	write_pack(&earlier, "earlier", &[]);
+	Unpacker::new(ks.env.clone(), &earlier).load().unwrap().unpack().unwrap();
+	let registry = fs::read(ks.env.registry_file()).unwrap();
+
+	// a file in place of the mask directory makes installing the second asset fail
+	let mask_dir = ks.env.custom_asset_dir(false).join(TextureType::Mask.path_name());
+	fs::remove_dir(&mask_dir).unwrap();
+	fs::write(&mask_dir, "").unwrap();
+
+	let before = ks.data_files();
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	assert!(matches!(packed.unpack(), Err(UnpackError::InstallError { .. })));
+
+	assert_eq!(ks.data_files(), before);
+	assert_eq!(fs::read_to_string(&preset).unwrap(), "old preset");
+	assert_eq!(fs::read_to_string(&first_target).unwrap(), "old first");
+	// the registry is part of the transaction, and does not record the failed import
+	assert_eq!(fs::read(ks.env.registry_file()).unwrap(), registry);
+}
+
+#[test]
+fn resolves_conflicts_per_asset() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("conflicts");
+
+	let names = ["overwrite", "keep", "rename", "identical", "different"];
+	let assets: Vec<_> = names.iter().map(|name| meta_entry(name, format!("new {}", name).as_bytes())).collect();
+	let data: Vec<_> = names.iter().map(|name| format!("new {}", name)).collect();
+	let entries: Vec<_> = assets.iter().map(|a| format!("assets/{}", a.hash)).collect();
+
+	let mut preset = preset_fixture();
+	let material = &mut preset["scene"]["backdropMaterial"];
+	material["diffuseTexture"] = serde_json::json!("rename");
+	material["emissiveTexture"] = serde_json::json!("rename");
+	material["normalTexture"] = serde_json::json!("rename");
+	let preset = serde_json::to_vec(&preset).unwrap();
+	let mut zip_entries: Vec<(&str, &[u8])> = vec![("preset.json", &preset)];
+	zip_entries.extend(entries.iter().map(String::as_str).zip(data.iter().map(String::as_bytes)));
+	craft_pack(&path, &serde_json::to_string(&metadata("conflicts", assets)).unwrap(), &zip_entries);
+
+	let colour = ks.env.custom_asset_dir(false).join(TextureType::Diffuse.path_name());
+	for name in names {
+		let old = if name == "identical" { String::from("new identical") } else { format!("old {}", name) };
+		fs::write(colour.join(format!("{}.png", name)), old).unwrap();
+	}
+
+	let mut packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	assert_eq!(packed.conflicts().len(), names.len());
+	let resolutions = [
+		Resolution::Overwrite,
+		Resolution::KeepExisting,
+		Resolution::Rename,
+		Resolution::SkipIdentical,
+		Resolution::SkipIdentical,
+	];
+	for (conflict, resolution) in packed.conflicts_mut().iter_mut().zip(resolutions) {
+		conflict.resolution = resolution;
+	}
+	packed.unpack().unwrap();
+
+	let read = |name: &str| fs::read_to_string(colour.join(format!("{}.png", name))).unwrap();
+	assert_eq!(read("overwrite"), "new overwrite");
+	assert_eq!(read("keep"), "old keep");
+	assert_eq!(read("rename"), "old rename");
+	assert_eq!(read("rename (1)"), "new rename");
+	assert_eq!(read("identical"), "new identical");
+	assert_eq!(read("different"), "new different");
+
+	let installed: serde_json::Value =
+		serde_json::from_slice(&fs::read(ks.env.custom_preset_dir().join("conflicts.json")).unwrap()).unwrap();
+	let material = &installed["scene"]["backdropMaterial"];
+	assert_eq!(material["diffuseTexture"], "rename (1)");
+	assert_eq!(material["emissiveTexture"], "rename (1)");
+	assert_eq!(material["normalTexture"], "rename");
+}
+
+#[test]
+fn classifies_existing_assets_by_content() {
+	let ks = FakeKeysight::new();
+	let path = ks.pack_path("report");
+	write_pack(&path, "report", &[
+		("absent", b"absent"),
+		("same", b"same"),
+		("same random", b"same random"),
+		("differs random", b"differs random"),
+		("mixed", b"mixed"),
+	]);
+
+	let dir = |random| ks.env.custom_asset_dir(random).join(TextureType::Diffuse.path_name());
+	fs::write(dir(false).join("same.png"), "same").unwrap();
+	fs::write(dir(true).join("same random.png"), "same random").unwrap();
+	fs::write(dir(true).join("differs random.png"), "other").unwrap();
+	fs::write(dir(false).join("mixed.png"), "mixed").unwrap();
+	fs::write(dir(true).join("mixed.png"), "other").unwrap();
+
+	let packed = Unpacker::new(ks.env.clone(), &path).load().unwrap();
+	let report = packed.report();
+	assert_eq!(report.statuses, [
+		AssetStatus::Absent,
+		AssetStatus::Identical,
+		AssetStatus::Identical,
+		AssetStatus::Differing,
+		AssetStatus::Differing,
+	]);
+	assert_eq!(report.count(AssetStatus::Identical), 2);
+
+	let conflict = |idx| report.conflicts.iter().find(|c| c.asset == idx).unwrap();
+	assert!(conflict(2).existing.iter().all(|e| e.random && e.identical));
+	let mixed: Vec<_> = conflict(4).existing.iter().map(|e| (e.random, e.identical)).collect();
+	assert_eq!(mixed, [(false, true), (true, false)]);
+
+	// only differing assets are overwritten by default
+	assert_eq!(conflict(1).resolution, Resolution::SkipIdentical);
+	assert!(!conflict(1).may_overwrite());
+	assert!(conflict(3).may_overwrite());
+}
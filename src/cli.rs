@@ -0,0 +1,616 @@
+//! Headless command line interface, used when kspacker is started with a subcommand.
+use std::{
+	path::{Path, PathBuf},
+	process::ExitCode,
+};
+
+use kspacker_core::{
+	helpers,
+	steam,
+	texture,
+	CollectPolicy,
+	CompressionProfile,
+	EnvError,
+	ExtraMeta,
+	KeysightEnv,
+	AssetStatus,
+	PackError,
+	PackOptions,
+	PackablePreset,
+	Packer,
+	Registry,
+	RegistryError,
+	Resolution,
+	UnpackError,
+	UnpackLimits,
+	Unpacker,
+	Version,
+};
+
+/// Exit codes returned by the cli
+pub mod exit {
+	pub const SUCCESS: u8 = 0;
+	/// Packing or unpacking failed
+	pub const FAILURE: u8 = 1;
+	// 2 is used by clap for usage errors
+	/// The keysight installation could not be found or read
+	pub const KEYSIGHT: u8 = 3;
+	/// The import would overwrite existing files and `--force` was not given
+	pub const CONFLICT: u8 = 4;
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(name = "kspacker", version, about = "Keysight Preset Packer")]
+pub struct Args {
+	#[clap(flatten)]
+	pub global: GlobalArgs,
+
+	/// Run a command without opening the window
+	#[clap(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct GlobalArgs {
+	/// Full path to the keysight installation directory, detected from the steam libraries if
+	/// not given
+	#[clap(short, long, global = true, env = "KSPACKER_KEYSIGHT_PATH")]
+	pub keysight_path: Option<PathBuf>,
+
+	/// Local data directory containing `Keysight/Saved`, defaults to the one of the current user
+	#[clap(long, global = true, env = "KSPACKER_DATA_DIR")]
+	pub data_dir: Option<PathBuf>,
+
+	/// Use the keysight installation and prefix found in the steam libraries, for running
+	/// keysight through proton. Detected proton installations use their prefix by default
+	#[clap(long, global = true)]
+	pub proton: bool,
+
+	/// Steam client or library directory to search, defaults to the usual install locations
+	#[clap(long, global = true, env = "KSPACKER_STEAM_ROOT")]
+	pub steam_root: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+	/// Pack saved presets and their custom assets into a preset file, several presets are
+	/// packed into a bundle
+	Pack {
+		/// Names of the saved presets
+		#[clap(required = true)]
+		presets: Vec<String>,
+		/// The file to write the packed preset to
+		#[clap(short, long)]
+		output: PathBuf,
+		/// Name the preset will be imported as, or the name of the bundle
+		#[clap(long)]
+		name: Option<String>,
+		#[clap(long, default_value = "")]
+		author: String,
+		#[clap(long, default_value = "")]
+		description: String,
+		/// Version of the preset
+		#[clap(long = "preset-version", default_value_t = 0)]
+		version: u32,
+		/// Allow packing builtin presets
+		#[clap(long)]
+		allow_builtin: bool,
+		/// Which of the referenced textures to include
+		#[clap(long, arg_enum, default_value_t = Collect::EnabledOnly)]
+		collect: Collect,
+		/// Fail if a referenced texture cannot be found, instead of packing without it
+		#[clap(long)]
+		strict: bool,
+		/// Texture extensions to look for, in order of preference
+		#[clap(long, use_value_delimiter = true, default_values = texture::DEFAULT_EXTENSIONS)]
+		extensions: Vec<String>,
+		/// Recompress png textures losslessly and strip exif and icc metadata
+		#[clap(long)]
+		optimise: bool,
+		/// Scale textures larger than this many pixels down to fit, implies `--optimise`
+		#[clap(long, value_name = "PIXELS")]
+		max_size: Option<u32>,
+		/// How hard to compress the package, textures that do not compress are always stored
+		#[clap(long, arg_enum, default_value_t = Compression::Balanced)]
+		compression: Compression,
+		/// Compress with deflate instead of zstd, for zip tools that cannot open zstd entries
+		#[clap(long)]
+		compatible: bool,
+	},
+	/// Import a preset file
+	Unpack {
+		file:   PathBuf,
+		/// Overwrite existing presets and assets
+		#[clap(short, long)]
+		force:       bool,
+		/// How to install assets that differ from an existing file of the same name, identical
+		/// ones are always skipped
+		#[clap(long, arg_enum, default_value_t = OnConflict::Overwrite)]
+		on_conflict: OnConflict,
+		/// Only import the named presets of a bundle, may be repeated
+		#[clap(long = "preset")]
+		presets:     Vec<String>,
+		#[clap(flatten)]
+		limits:      LimitArgs,
+	},
+	/// Show the metadata of a preset file
+	Info {
+		file:   PathBuf,
+		#[clap(flatten)]
+		limits: LimitArgs,
+	},
+	/// Check the assets of a preset file against their recorded hashes
+	Verify {
+		file:   PathBuf,
+		#[clap(flatten)]
+		limits: LimitArgs,
+	},
+	/// List all saved presets
+	List,
+	/// List the imported preset files
+	Installed,
+	/// Show the files an imported preset file installed
+	Files {
+		/// Name of the imported preset
+		name: String,
+	},
+	/// Remove an imported preset and the assets no other imported preset uses
+	Uninstall {
+		/// Name of the imported preset
+		name: String,
+	},
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+pub enum OnConflict {
+	/// Replace the existing asset
+	Overwrite,
+	/// Keep the existing asset and skip the packed one
+	Keep,
+	/// Import the packed asset under a new name
+	Rename,
+	/// Skip assets identical to the existing one, overwrite the others
+	SkipIdentical,
+}
+
+impl From<OnConflict> for Resolution {
+	fn from(arg: OnConflict) -> Self {
+		match arg {
+			OnConflict::Overwrite => Resolution::Overwrite,
+			OnConflict::Keep => Resolution::KeepExisting,
+			OnConflict::Rename => Resolution::Rename,
+			OnConflict::SkipIdentical => Resolution::SkipIdentical,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+pub enum Collect {
+	/// Only textures the preset currently uses
+	EnabledOnly,
+	/// Also textures of disabled particles, pulses and material slots
+	AllReferenced,
+}
+
+impl From<Collect> for CollectPolicy {
+	fn from(arg: Collect) -> Self {
+		match arg {
+			Collect::EnabledOnly => CollectPolicy::EnabledOnly,
+			Collect::AllReferenced => CollectPolicy::AllReferenced,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+pub enum Compression {
+	Fast,
+	Balanced,
+	Max,
+	/// Store entries uncompressed
+	StoreOnly,
+}
+
+impl From<Compression> for CompressionProfile {
+	fn from(arg: Compression) -> Self {
+		match arg {
+			Compression::Fast => CompressionProfile::Fast,
+			Compression::Balanced => CompressionProfile::Balanced,
+			Compression::Max => CompressionProfile::Max,
+			Compression::StoreOnly => CompressionProfile::StoreOnly,
+		}
+	}
+}
+
+/// Limits for reading untrusted preset files, sizes are in MiB
+#[derive(Debug, clap::Args)]
+pub struct LimitArgs {
+	/// Maximum uncompressed size of the whole package
+	#[clap(long)]
+	max_total_mib:         Option<u64>,
+	/// Maximum uncompressed size of a single asset
+	#[clap(long)]
+	max_asset_mib:         Option<u64>,
+	/// Maximum number of entries in the package
+	#[clap(long)]
+	max_entries:           Option<usize>,
+	/// Maximum compression ratio of a single entry
+	#[clap(long)]
+	max_compression_ratio: Option<u64>,
+	/// Maximum size of the metadata and preset json
+	#[clap(long)]
+	max_json_mib:          Option<u64>,
+}
+
+impl From<LimitArgs> for UnpackLimits {
+	fn from(args: LimitArgs) -> Self {
+		const MIB: u64 = 1024 * 1024;
+		let default = UnpackLimits::default();
+		// sizes too large to count in bytes do not limit anything
+		let mib = |v: u64| v.saturating_mul(MIB);
+
+		UnpackLimits {
+			max_total_size:        args.max_total_mib.map_or(default.max_total_size, mib),
+			max_asset_size:        args.max_asset_mib.map_or(default.max_asset_size, mib),
+			max_entries:           args.max_entries.unwrap_or(default.max_entries),
+			max_compression_ratio: args.max_compression_ratio.unwrap_or(default.max_compression_ratio),
+			max_json_size:         args.max_json_mib.map_or(default.max_json_size, mib),
+		}
+	}
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+enum CliError {
+	#[error(transparent)]
+	#[diagnostic(transparent)]
+	Pack(#[from] PackError),
+
+	#[error(transparent)]
+	#[diagnostic(transparent)]
+	Unpack(#[from] UnpackError),
+
+	#[error(transparent)]
+	#[diagnostic(transparent)]
+	Env(#[from] EnvError),
+
+	#[error(transparent)]
+	#[diagnostic(transparent)]
+	Registry(#[from] RegistryError),
+
+	#[error("keysight path not set and no installation was found")]
+	#[diagnostic(
+		code(cli::keysight::missing),
+		help("Pass --keysight-path or set KSPACKER_KEYSIGHT_PATH")
+	)]
+	NoKeysightPath,
+
+	#[error("no proton installation of keysight found")]
+	#[diagnostic(
+		code(cli::keysight::no_proton),
+		help("Pass --steam-root if steam is installed in an unusual location")
+	)]
+	NoProtonInstall,
+
+	#[error("{reason:#}")]
+	#[diagnostic(code(cli::keysight::invalid))]
+	InvalidKeysightPath { reason: anyhow::Error },
+
+	#[error("cannot list presets")]
+	#[diagnostic(code(cli::list::io))]
+	ListError {
+		#[source]
+		reason: std::io::Error,
+	},
+
+	#[error("bundle contains no preset named `{name}`")]
+	#[diagnostic(code(cli::unpack::no_preset), help("Run `kspacker info` to list the presets"))]
+	NoSuchPreset { name: String },
+
+	#[error("importing `{name}` would overwrite {count} existing file(s)")]
+	#[diagnostic(
+		code(cli::unpack::conflict),
+		help("Pass --force to overwrite them, or choose another --on-conflict")
+	)]
+	Conflict { name: String, count: usize },
+}
+
+impl CliError {
+	fn exit_code(&self) -> u8 {
+		match self {
+			CliError::Pack(PackError::WrongVersion { .. })
+			| CliError::Env(_)
+			| CliError::NoKeysightPath
+			| CliError::NoProtonInstall
+			| CliError::InvalidKeysightPath { .. } => exit::KEYSIGHT,
+			CliError::Conflict { .. } => exit::CONFLICT,
+			_ => exit::FAILURE,
+		}
+	}
+}
+
+pub fn run(global: GlobalArgs, command: Command) -> ExitCode {
+	match run_command(global, command) {
+		Ok(()) => ExitCode::from(exit::SUCCESS),
+		Err(why) => {
+			let code = why.exit_code();
+			eprintln!("{:?}", miette::Report::new(why));
+			ExitCode::from(code)
+		},
+	}
+}
+
+fn run_command(global: GlobalArgs, command: Command) -> Result<(), CliError> {
+	match command {
+		Command::Pack {
+			presets,
+			output,
+			name,
+			author,
+			description,
+			version,
+			allow_builtin,
+			collect,
+			strict,
+			extensions,
+			optimise,
+			max_size,
+			compression,
+			compatible,
+		} => {
+			let env = keysight_env(global, true)?;
+			let ksv = ks_version(env.install_root())?;
+
+			let mut packable = Vec::with_capacity(presets.len());
+			for preset in &presets {
+				let ppreset = Packer::new(env.clone(), ksv, preset)
+					.policy(collect.into())
+					.strict(strict)
+					.extensions(&extensions)
+					.collect(allow_builtin)?;
+				for asset in ppreset.assets() {
+					let disabled = if asset.active { "" } else { ", disabled" };
+					println!("including {}.{} ({:?}{})", asset.name, asset.ext, asset.texture_type, disabled);
+					for other in &asset.candidates {
+						eprintln!("warning: ignoring {}, {}.{} is used instead", other.display(), asset.name, asset.ext);
+					}
+				}
+				for asset in ppreset.builtin() {
+					println!("using builtin {}.{} ({:?})", asset.name, asset.ext, asset.texture_type);
+				}
+				for asset in ppreset.missing() {
+					eprintln!("warning: {} ({:?}) not found, it will be blank", asset.name, asset.texture_type);
+				}
+				packable.push(ppreset);
+			}
+
+			let extra_meta =
+				ExtraMeta { rename: name, author, description, version, current_ks_version: ksv };
+			let options = PackOptions {
+				optimise:    (optimise || max_size.is_some()).then_some(texture::Optimise { max_size }),
+				compression: compression.into(),
+				compatible,
+				..PackOptions::default()
+			};
+			let summary = if let [ppreset] = packable.as_slice() {
+				let summary = ppreset.pack(&output, extra_meta, &options)?;
+				println!("exported preset `{}` to {}", ppreset.name(), output.display());
+				summary
+			} else {
+				let summary = PackablePreset::pack_bundle(&packable, &output, extra_meta, &options)?;
+				println!("exported {} presets to {}", packable.len(), output.display());
+				summary
+			};
+			for warning in &summary.warnings {
+				eprintln!("warning: {}: {}", warning.path.display(), warning.warning);
+			}
+			if options.optimise.is_some() {
+				println!(
+					"optimised {} texture(s) from {} to {}, saving {}",
+					summary.optimised,
+					helpers::format_size(summary.original_size),
+					helpers::format_size(summary.packed_size),
+					helpers::format_size(summary.saved())
+				);
+			}
+		},
+		Command::Unpack { file, force, on_conflict, presets, limits } => {
+			let env = keysight_env(global, false)?;
+			// the installation is only used to warn about presets packed for another version
+			let ksv = kspacker_core::get_ks_version(env.install_root()).ok();
+			let mut bundle = Unpacker::new(env, &file).limits(limits.into()).load_bundle()?;
+
+			let selection = if presets.is_empty() {
+				(0..bundle.presets().len()).collect()
+			} else {
+				presets
+					.into_iter()
+					.map(|name| {
+						bundle
+							.presets()
+							.iter()
+							.position(|p| p.metadata().name == name)
+							.ok_or(CliError::NoSuchPreset { name })
+					})
+					.collect::<Result<Vec<_>, _>>()?
+			};
+
+			for &idx in &selection {
+				let packed = &mut bundle.presets_mut()[idx];
+				for conflict in packed.conflicts_mut() {
+					if conflict.status == AssetStatus::Differing {
+						conflict.resolution = on_conflict.into();
+					}
+				}
+				let meta = packed.metadata();
+
+				if ksv.is_some_and(|ksv| ksv != meta.target_version) {
+					eprintln!(
+						"warning: preset `{}` was packed for keysight {}, but {} is installed",
+						meta.name,
+						helpers::maybe_format_version(Some(meta.target_version)),
+						helpers::maybe_format_version(ksv)
+					);
+				}
+
+				let overwritten = packed.conflicts().iter().filter(|c| c.may_overwrite()).count();
+				let count = overwritten + usize::from(packed.exists());
+				if count > 0 && !force {
+					return Err(CliError::Conflict { name: meta.name.clone(), count });
+				}
+			}
+
+			for pack in bundle.unpack(&selection)? {
+				println!("imported preset `{}`", pack.name());
+			}
+		},
+		Command::Info { file, limits } => {
+			let env = keysight_env(global, false)?;
+			let bundle = Unpacker::new(env, &file).limits(limits.into()).load_bundle()?;
+			let info = bundle.info();
+
+			if bundle.presets().len() > 1 {
+				println!("Bundle:           {}", info.name);
+				println!("Author:           {}", info.author);
+				println!("Description:      {}", info.description);
+				println!("Packed on:        {}", info.packed.format("%F %T"));
+				println!("Presets:          {}", bundle.presets().len());
+			}
+
+			for packed in bundle.presets() {
+				let meta = packed.metadata();
+				if bundle.presets().len() > 1 {
+					println!();
+				}
+
+				println!("Name:             {}", meta.name);
+				println!("Version:          {:#X}", meta.preset_version);
+				println!("Keysight Version: {:#X}", meta.target_version);
+				println!("Author:           {}", meta.author);
+				println!("Description:      {}", meta.description);
+				println!("Packed on:        {}", meta.packed.format("%F %T"));
+				println!("Format:           {} (kspacker ≥ {})", meta.format_version, meta.requires);
+				println!("Preset exists:    {}", if packed.exists() { "yes" } else { "no" });
+
+				println!("Assets:");
+				for (entry, status) in meta.assets.iter().zip(&packed.report().statuses) {
+					let conflict = match status {
+						AssetStatus::Absent => "",
+						AssetStatus::Identical => " (installed)",
+						AssetStatus::Differing => " (conflict)",
+					};
+					println!(
+						"  {}.{} {:?} {} {}{}",
+						entry.name, entry.extension, entry.texture_type, entry.format, entry.hash, conflict
+					);
+					if let Some(image) = entry.image {
+						let mut notes = String::new();
+						if !image.is_power_of_two() {
+							notes.push_str(", not a power of two");
+						}
+						if image.is_oversized() {
+							notes.push_str(", oversized");
+						}
+						println!("    {}{}", image, notes);
+					}
+				}
+			}
+		},
+		Command::Verify { file, limits } => {
+			let env = keysight_env(global, false)?;
+			let bundle = Unpacker::new(env, &file).limits(limits.into()).load_bundle()?;
+			bundle.verify()?;
+			for packed in bundle.presets() {
+				println!("all {} asset(s) of `{}` are intact", packed.metadata().assets.len(), packed.metadata().name);
+			}
+		},
+		Command::List => {
+			let env = keysight_env(global, false)?;
+			let mut presets =
+				helpers::list_all_presets(&env).map_err(|reason| CliError::ListError { reason })?;
+			presets.sort();
+			for preset in presets {
+				println!("{}", preset);
+			}
+		},
+		Command::Installed => {
+			let registry = Registry::load(&keysight_env(global, false)?)?;
+			for pack in registry.packs() {
+				println!(
+					"{} {:#X} by {} (installed {})",
+					pack.name(),
+					pack.metadata.preset_version,
+					pack.metadata.author,
+					pack.installed.format("%F %T")
+				);
+			}
+		},
+		Command::Files { name } => {
+			let registry = Registry::load(&keysight_env(global, false)?)?;
+			let pack = registry.find(&name).ok_or(RegistryError::NotInstalled { name })?;
+
+			println!("{}", registry.resolve(&pack.preset).display());
+			for asset in &pack.assets {
+				let kept = if asset.written { "" } else { " (existing)" };
+				println!("{}{}", registry.resolve(&asset.path).display(), kept);
+			}
+		},
+		Command::Uninstall { name } => {
+			let mut registry = Registry::load(&keysight_env(global, false)?)?;
+			let uninstalled = registry.uninstall(&name)?;
+			registry.save()?;
+
+			for path in &uninstalled.shared {
+				println!("kept {}, used by another preset", registry.resolve(path).display());
+			}
+			println!("removed preset `{}` and {} file(s)", name, uninstalled.removed.len());
+		},
+	}
+
+	Ok(())
+}
+
+/// Resolves the keysight installation and data directory. Packages are inspected using only the
+/// data directory, so the installation is only required if `install` is set.
+fn keysight_env(global: GlobalArgs, install: bool) -> Result<KeysightEnv, CliError> {
+	let steam_roots = match global.steam_root {
+		Some(root) => vec![root],
+		None => steam::default_steam_roots(),
+	};
+
+	let proton_installs = steam::discover_proton(&steam_roots);
+	if global.proton && proton_installs.is_empty() {
+		return Err(CliError::NoProtonInstall);
+	}
+
+	// explicitly given paths take precedence over the discovered ones
+	let root = match global.keysight_path {
+		Some(root) => root,
+		None if global.proton => proton_installs[0].install_root().to_owned(),
+		None => match steam::detect_installations(&steam_roots).into_iter().next() {
+			Some(detected) => {
+				info!(root=%detected.install_root.display(), "using detected installation");
+				detected.install_root
+			},
+			None if install => return Err(CliError::NoKeysightPath),
+			None => {
+				debug!("no installation found, only using the data directory");
+				PathBuf::new()
+			},
+		},
+	};
+
+	let proton = if global.proton {
+		proton_installs.first()
+	} else {
+		proton_installs.iter().find(|env| env.install_root() == root)
+	};
+	let data_root = global.data_dir.or_else(|| proton.map(|env| env.data_root().to_owned()));
+
+	match data_root {
+		Some(data_root) => Ok(KeysightEnv::new(root, data_root)),
+		None => Ok(KeysightEnv::with_default_data(root)?),
+	}
+}
+
+fn ks_version(root: &Path) -> Result<Version, CliError> {
+	kspacker_core::get_ks_version(root).map_err(|reason| CliError::InvalidKeysightPath { reason })
+}
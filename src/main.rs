@@ -2,16 +2,44 @@
 #[macro_use]
 extern crate tracing;
 
-mod pack;
+mod cli;
 mod structs;
 
+use std::{
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	thread,
+};
+
+use clap::Parser;
+
 use eframe::{
 	egui::{self, RichText},
 	epaint::Color32,
 };
-use pack::{
-	packer::{ExtraMeta, PackablePreset},
-	unpacker::PackedFile,
+use kspacker_core::{
+	helpers,
+	steam,
+	texture,
+	CollectPolicy,
+	CompressionProfile,
+	ExtraMeta,
+	InstalledPack,
+	KeysightEnv,
+	PackError,
+	PackOptions,
+	PackSummary,
+	PackablePreset,
+	AssetStatus,
+	PackedBundle,
+	PackedFile,
+	Packer,
+	Progress,
+	ProgressUpdate,
+	Registry,
+	Resolution,
+	UnpackError,
+	Unpacker,
 	Version,
 };
 
@@ -20,10 +48,17 @@ const PRESET_EXT_NAME: &str = "Keysight Preset";
 const APP_PERSIST_KEY: &str = "ks-packer-data";
 const DEFAULT_EXPORT_KEY: &str = "[Select Preset]";
 
-fn main() {
+fn main() -> std::process::ExitCode {
+	// release builds on windows have no console of their own, when started with arguments the
+	// cli and its usage messages print to the console it was started from
+	#[cfg(all(windows, not(debug_assertions)))]
+	if std::env::args_os().len() > 1 {
+		attach_parent_console();
+	}
+
 	#[cfg(debug_assertions)]
 	{
-		std::env::set_var("RUST_LOG", concat!("info,", env!("CARGO_PKG_NAME"), "=trace"));
+		std::env::set_var("RUST_LOG", concat!("info,", env!("CARGO_PKG_NAME"), "=trace,kspacker_core=trace"));
 	}
 
 	tracing_subscriber::fmt()
@@ -31,9 +66,15 @@ fn main() {
 		.with_file(true)
 		.with_line_number(true)
 		.with_thread_names(true)
+		.with_writer(std::io::stderr)
 		.with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
 		.init();
 
+	let args = cli::Args::parse();
+	if let Some(command) = args.command {
+		return cli::run(args.global, command);
+	}
+
 	let egui_opts = eframe::NativeOptions {
 		resizable: false,
 		initial_window_size: Some(eframe::emath::vec2(600.0, 800.0)),
@@ -44,9 +85,24 @@ fn main() {
 	eframe::run_native("kspacker", egui_opts, Box::new(|cc| Box::new(App::new(cc))));
 }
 
+/// Attaches to the console of the parent process, if it has one
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_parent_console() {
+	const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn AttachConsole(process_id: u32) -> i32;
+	}
+
+	// fails when started from explorer, which has no console to print to anyway
+	unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
 struct App {
 	persisted:          PersistedState,
 	current_error:      Option<String>,
+	current_env:        Option<KeysightEnv>,
 	current_ks_version: Option<Version>,
 	current_tab:        ActionTab,
 	debug:              bool,
@@ -54,21 +110,28 @@ struct App {
 
 	status_message: Option<Message>,
 
-	known_presets: Vec<String>,
+	known_presets:   Vec<String>,
+	installations:   Vec<steam::Installation>,
+	proton_installs: Vec<KeysightEnv>,
 
-	import: ImportState,
-	export: ExportState,
+	import:    ImportState,
+	export:    ExportState,
+	installed: Option<Registry>,
+	/// The work running in the background, the ui only shows its progress
+	task:      Option<Task>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct PersistedState {
 	keysight_path: String,
+	#[serde(default)]
+	data_path:     String,
 	firstrun: bool,
 }
 
 impl Default for PersistedState {
 	fn default() -> Self {
-		Self { keysight_path: String::default(), firstrun: true }
+		Self { keysight_path: String::default(), data_path: String::default(), firstrun: true }
 	}
 }
 
@@ -76,26 +139,104 @@ impl Default for PersistedState {
 enum ActionTab {
 	Import,
 	Export,
+	Installed,
 }
 
 #[derive(Default)]
 struct ImportState {
-	path: String,
-	pack: Option<PackedFile>,
+	path:     String,
+	bundle:   Option<PackedBundle>,
+	/// Which presets of the bundle to import
+	selected: Vec<bool>,
 
 	error_confirmed: bool,
 }
 
 #[derive(Default)]
 struct ExportState {
-	current_preset_selection: usize,
-
 	e_name:        String,
 	e_author:      String,
 	e_description: String,
 	e_version:     u32,
 
-	packable_preset: Option<PackablePreset>,
+	/// Which referenced textures of the selected presets are included
+	policy:           CollectPolicy,
+	/// The selected presets, packed into a bundle if there are several
+	packable_presets: Vec<PackablePreset>,
+	/// Optimise textures when exporting
+	optimise:         bool,
+	/// Scale textures down to this size when optimising
+	max_size:         Option<u32>,
+	compression:      CompressionProfile,
+	/// Compress with deflate, for zip tools without zstd support
+	compatible:       bool,
+}
+
+/// An export, import or other slow work running on a worker thread
+struct Task {
+	/// What is running, shown above the progress bar
+	label:    String,
+	progress: Progress,
+	latest:   Arc<Mutex<Option<ProgressUpdate>>>,
+	handle:   thread::JoinHandle<TaskResult>,
+}
+
+/// The outcome of a [`Task`], with the state it took so it can be restored if it failed
+enum TaskResult {
+	Export {
+		path:      PathBuf,
+		optimised: bool,
+		presets:   Vec<PackablePreset>,
+		result:    Result<PackSummary, PackError>,
+	},
+	Import {
+		bundle: PackedBundle,
+		result: Result<Vec<InstalledPack>, UnpackError>,
+	},
+	/// A package loaded and checked for conflicts, to be imported
+	Load {
+		result: Result<PackedBundle, UnpackError>,
+	},
+	/// Presets collected to be exported
+	Collect {
+		/// The policy before it changed, when the presets replace the selection
+		previous_policy: Option<CollectPolicy>,
+		results:         Vec<Result<PackablePreset, PackError>>,
+	},
+}
+
+impl Task {
+	/// Runs `run` on a new thread, repainting the ui on every progress update
+	fn spawn(
+		ctx: &egui::Context,
+		label: String,
+		run: impl FnOnce(&Progress) -> TaskResult + Send + 'static,
+	) -> Self {
+		let latest = Arc::new(Mutex::new(None));
+		let progress = Progress::new({
+			let latest = latest.clone();
+			let ctx = ctx.clone();
+			move |update| {
+				*latest.lock().unwrap() = Some(update.clone());
+				ctx.request_repaint();
+			}
+		});
+
+		let handle = thread::Builder::new()
+			.name(String::from("kspacker-worker"))
+			.spawn({
+				let progress = progress.clone();
+				let ctx = ctx.clone();
+				move || {
+					let result = run(&progress);
+					ctx.request_repaint();
+					result
+				}
+			})
+			.expect("failed to spawn the worker thread");
+
+		Self { label, progress, latest, handle }
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -121,14 +262,32 @@ impl App {
 		let is_first_run = pers_state.firstrun;
 		pers_state.firstrun = false;
 
+		let steam_roots = steam::default_steam_roots();
+		let installations = steam::detect_installations(&steam_roots);
+		let proton_installs = steam::discover_proton(&steam_roots);
+
+		if pers_state.keysight_path.is_empty() {
+			if let Some(install) = installations.first() {
+				pers_state.keysight_path = install.install_root.display().to_string();
+				if let Some(env) = proton_installs.iter().find(|env| env.install_root() == install.install_root) {
+					pers_state.data_path = env.data_root().display().to_string();
+				}
+			}
+		}
+
 		Self {
 			import:             ImportState::default(),
 			export:             ExportState::default(),
+			installed:          None,
+			task:               None,
 			current_error:      None,
+			current_env:        None,
 			current_ks_version: None,
 			current_tab:        ActionTab::Import,
 			status_message:     None,
 			known_presets:      vec![DEFAULT_EXPORT_KEY.to_string()],
+			installations,
+			proton_installs,
 			persisted:          pers_state,
 			debug:              std::env::var("KSPACKER_DEBUG").map(|v| v == "1").unwrap_or(false),
 			help:               is_first_run
@@ -141,12 +300,17 @@ impl eframe::App for App {
 		_frame.set_window_title("Keysight Preset Packer");
 		egui::CentralPanel::default().show(ctx, |ui| {
 			if self.debug {
-				egui::Window::new("Path Debug").show(ctx, |ui| {
-					ui.label(format!(
-						"custom preset = {}\ncustom asset = {}",
-						pack::helpers::custom_preset_dir().display(),
-						pack::helpers::custom_asset_dir(false).display()
-					));
+				egui::Window::new("Path Debug").show(ctx, |ui| match self.current_env.as_ref() {
+					Some(env) => {
+						ui.label(format!(
+							"custom preset = {}\ncustom asset = {}",
+							env.custom_preset_dir().display(),
+							env.custom_asset_dir(false).display()
+						));
+					},
+					None => {
+						ui.label("keysight path not set");
+					},
 				});
 			}
 
@@ -186,6 +350,19 @@ impl eframe::App for App {
 						ui.code("Engine");
 						ui.label("and");
 						ui.code("Keysight");
+						ui.label(". Installations found in your steam libraries are listed under");
+						let _ = ui.small_button("Detected");
+						ui.label("so you usually do not need to enter it yourself.");
+					});
+
+					ui.separator();
+					ui.heading("Data Path");
+					ui.horizontal_wrapped(|ui| {
+						ui.label("The data path is the directory keysight saves your presets and textures in. Leave it empty to use the default");
+						ui.code(r"%LOCALAPPDATA%");
+						ui.label("directory. When running keysight through proton, selecting the installation under");
+						let _ = ui.small_button("Detected");
+						ui.label("will also set the data path of its prefix.");
 					});
 
 					ui.separator();
@@ -197,6 +374,16 @@ impl eframe::App for App {
 
 			ui.heading("Keysight Preset Packer - by HeapUnderflow");
 
+			if self.task.as_ref().is_some_and(|task| task.handle.is_finished()) {
+				let task = self.task.take().unwrap();
+				self.finish_task(task);
+			}
+
+			if let Some(task) = &self.task {
+				task_ui(ui, task);
+				return;
+			}
+
 			if let Some(message) = self.status_message.clone() {
 				ui.vertical_centered(|ui| {
 					match message {
@@ -227,13 +414,49 @@ impl eframe::App for App {
 					}
 				}
 
+				ui.menu_button("Detected", |ui| {
+					if self.installations.is_empty() {
+						ui.label("No installation found");
+					}
+
+					for install in &self.installations {
+						let proton =
+							self.proton_installs.iter().find(|env| env.install_root() == install.install_root);
+
+						let label = format!(
+							"{} ({}{})",
+							install.install_root.display(),
+							helpers::maybe_format_version(Some(install.version)),
+							if proton.is_some() { ", proton" } else { "" }
+						);
+
+						if ui.button(label).clicked() {
+							self.persisted.keysight_path = install.install_root.display().to_string();
+							self.persisted.data_path =
+								proton.map(|env| env.data_root().display().to_string()).unwrap_or_default();
+							ui.close_menu();
+						}
+					}
+				});
+
 				if ui.button("Set").clicked() && !self.persisted.keysight_path.is_empty() {
-					match pack::get_ks_version(&self.persisted.keysight_path) {
-						Ok(v) => match pack::helpers::list_all_presets() {
-							Ok(presets) => {
-								self.current_ks_version = Some(v);
-								self.known_presets = presets;
-								self.known_presets.insert(0, DEFAULT_EXPORT_KEY.to_string());
+					let env = if self.persisted.data_path.is_empty() {
+						KeysightEnv::with_default_data(&self.persisted.keysight_path)
+					} else {
+						Ok(KeysightEnv::new(&self.persisted.keysight_path, &self.persisted.data_path))
+					};
+
+					match env {
+						Ok(env) => match kspacker_core::get_ks_version(env.install_root()) {
+							Ok(v) => match helpers::list_all_presets(&env) {
+								Ok(presets) => {
+									self.current_env = Some(env);
+									self.current_ks_version = Some(v);
+									self.installed = None;
+									self.known_presets = presets;
+									self.known_presets.insert(0, DEFAULT_EXPORT_KEY.to_string());
+								},
+								Err(why) => self.current_error = Some(format_error!(why)),
 							},
 							Err(why) => self.current_error = Some(format_error!(why)),
 						},
@@ -248,9 +471,20 @@ impl eframe::App for App {
 				ui.allocate_space(egui::Vec2::new(ui.available_width(), 0.0));
 			});
 
+			ui.horizontal(|ui| {
+				ui.label("Data Path:");
+				ui.text_edit_singleline(&mut self.persisted.data_path)
+					.on_hover_text("Leave empty to use the default local data directory");
+				if ui.button("Pick").clicked() {
+					if let Some(path) = rfd::FileDialog::new().pick_folder() {
+						self.persisted.data_path = path.display().to_string();
+					}
+				}
+			});
+
 			ui.label(format!(
 				"Keysight Version: {}",
-				pack::helpers::maybe_format_version(self.current_ks_version)
+				helpers::maybe_format_version(self.current_ks_version)
 			));
 
 			if let Some(error) = self.current_error.clone() {
@@ -268,6 +502,7 @@ impl eframe::App for App {
 			ui.horizontal(|ui| {
 				ui.radio_value(&mut self.current_tab, ActionTab::Import, "Import");
 				ui.radio_value(&mut self.current_tab, ActionTab::Export, "Export");
+				ui.radio_value(&mut self.current_tab, ActionTab::Installed, "Installed");
 			});
 
 			ui.add_enabled_ui(self.current_ks_version.is_some(), |ui| {
@@ -277,6 +512,7 @@ impl eframe::App for App {
 						match self.current_tab {
 							ActionTab::Import => self.import_ui(ui),
 							ActionTab::Export => self.export_ui(ui),
+							ActionTab::Installed => self.installed_ui(ui),
 						}
 
 						ui.allocate_space(egui::Vec2::new(ui.available_width(), 0.0));
@@ -310,101 +546,60 @@ impl App {
 				}
 			}
 			if pick_ui.button("Set").clicked() && !self.import.path.is_empty() {
-				match pack::unpacker::Unpacker::new(&self.import.path).load() {
-					Ok(preset) => self.import.pack = Some(preset),
-					Err(why) => self.current_error = Some(format_error!(why)),
-				}
+				// loading hashes the installed files the package would overwrite
+				let unpacker = Unpacker::new(self.current_env.clone().unwrap(), &self.import.path);
+				let label = format!("Loading {}", self.import.path);
+				self.task = Some(Task::spawn(pick_ui.ctx(), label, move |_| TaskResult::Load {
+					result: unpacker.load_bundle(),
+				}));
 			}
 		});
 
-		if let Some(preset) = self.import.pack.as_ref() {
-			let meta = preset.metadata();
+		if let Some(bundle) = self.import.bundle.as_mut() {
+			let ks_version = self.current_ks_version.unwrap();
+			let is_bundle = bundle.presets().len() > 1;
 
 			ui.separator();
 
-			ui.label("Loaded Preset:");
-			egui::Grid::new("kspack-import-preset-info").num_columns(2).striped(true).show(
-				ui,
-				|ui| {
-                    ui.wrap_text();
-
-					ui.label("Name");
-					ui.add(egui::Label::new(&meta.name).wrap(true));
-					ui.end_row();
-
-					ui.label("Version");
-					ui.label(format!("{:#X}", meta.preset_version));
-					ui.end_row();
-
-					ui.label("Keysight Version");
-					ui.label(RichText::new(format!("{:#X}", meta.target_version)).color(
-						if meta.target_version != self.current_ks_version.unwrap() {
-							Color32::RED
-						} else {
-							Color32::BLACK
-						},
-					));
-					ui.end_row();
-
-					ui.label("Author");
-					ui.add(egui::Label::new(&meta.author).wrap(true));
-					ui.end_row();
-
-					ui.label("Description");
-					ui.add(egui::Label::new(&meta.description).wrap(true));
-					ui.end_row();
-
-					ui.label("Packed on");
-					ui.label(&meta.packed.format("%F %T").to_string());
-					ui.end_row();
-				},
-			);
+			if is_bundle {
+				let info = bundle.info();
+				ui.label("Loaded Bundle:");
+				egui::Grid::new("kspack-import-bundle-info").num_columns(2).striped(true).show(
+					ui,
+					|ui| {
+						ui.label("Name");
+						ui.add(egui::Label::new(&info.name).wrap(true));
+						ui.end_row();
 
-			let exists = preset.exists();
-			let has_errors = !preset.conflicts().is_empty() || exists;
+						ui.label("Author");
+						ui.add(egui::Label::new(&info.author).wrap(true));
+						ui.end_row();
 
-			if has_errors {
-				ui.separator();
-			}
+						ui.label("Description");
+						ui.add(egui::Label::new(&info.description).wrap(true));
+						ui.end_row();
 
-			if exists {
-				ui.label(
-					RichText::new(
-						"Warning! A preset already exists under this name.\n    Please make sure \
-						 that you really want to overwrite it.",
-					)
-					.color(Color32::RED),
+						ui.label("Packed on");
+						ui.label(info.packed.format("%F %T").to_string());
+						ui.end_row();
+					},
 				);
 			}
 
-			if !preset.conflicts().is_empty() {
-				ui.label(
-					RichText::new(
-						"Warning! This preset has conflicting assets.\n    Please make sure that \
-						 you want to overwrite these assets!",
-					)
-					.color(Color32::RED),
-				);
-				ui.label("Conflicting Assets");
-				egui::Grid::new("kspack-import-conflict-list").num_columns(3).striped(true).show(
-					ui,
-					|ui| {
-						ui.label(RichText::new("File").strong().underline());
-						ui.label(RichText::new("Type").strong().underline());
-						ui.label(RichText::new("Hash").strong().underline());
-						ui.end_row();
+			let mut has_errors = false;
+			for (idx, preset) in bundle.presets_mut().iter_mut().enumerate() {
+				if is_bundle {
+					ui.separator();
+					ui.checkbox(
+						&mut self.import.selected[idx],
+						RichText::new(&preset.metadata().name).strong(),
+					);
+					if !self.import.selected[idx] {
+						continue;
+					}
+				}
 
-						for entry in preset.conflicts() {
-							ui.label(format!("{}.{}", entry.name, entry.extension));
-							ui.label(format!("{:?}", entry.texture_type));
-							ui.label(format!(
-								"{}...",
-								entry.hash.chars().take(16).collect::<String>()
-							));
-							ui.end_row();
-						}
-					},
-				);
+				has_errors |= packed_preset_ui(ui, idx, preset, ks_version);
 			}
 
 			ui.separator();
@@ -413,27 +608,27 @@ impl App {
 				ui.checkbox(
 					&mut self.import.error_confirmed,
 					"I have understood above conflicts and aknowledge that i want to overwrite \
-					 all specified files.",
+					 the existing files.",
 				);
 			}
 
+			let selection: Vec<usize> = (0..self.import.selected.len())
+				.filter(|idx| self.import.selected[*idx])
+				.collect();
+
 			if ui
 				.add_enabled(
-					!has_errors || self.import.error_confirmed,
+					!selection.is_empty() && (!has_errors || self.import.error_confirmed),
 					egui::Button::new("Import"),
 				)
 				.clicked()
 			{
-				if let Err(why) = preset.unpack() {
-					self.current_error = Some(format_error!(why));
-					self.import.error_confirmed = false;
-				} else {
-					let name = meta.name.clone();
-					self.import = ImportState::default();
-					self.status_message = Some(Message::Success {
-						message: format!("Successfully imported preset {}", name),
-					});
-				}
+				let bundle = self.import.bundle.take().unwrap();
+				let label = format!("Importing {}", bundle.info().name);
+				self.task = Some(Task::spawn(ui.ctx(), label, move |progress| {
+					let result = bundle.unpack_with(&selection, progress);
+					TaskResult::Import { bundle, result }
+				}));
 			}
 		}
 	}
@@ -442,39 +637,82 @@ impl App {
 		ui.heading("Export Preset");
 
 		ui.horizontal(|ui| {
-			ui.label("Select Preset: ");
-			let cbc = egui::ComboBox::from_id_source("kspack-export-preset-select")
+			ui.label("Select Presets: ");
+			let selected_text = match self.export.packable_presets.as_slice() {
+				[] => DEFAULT_EXPORT_KEY.to_owned(),
+				[preset] => preset.name().to_owned(),
+				presets => format!("{} presets", presets.len()),
+			};
+
+			let mut toggled = None;
+			egui::ComboBox::from_id_source("kspack-export-preset-select")
 				.width(ui.available_width())
-				.show_index(
-					ui,
-					&mut self.export.current_preset_selection,
-					self.known_presets.len(),
-					|idx| self.known_presets[idx].to_owned(),
-				)
-				.changed();
+				.selected_text(selected_text)
+				.show_ui(ui, |ui| {
+					for name in self.known_presets.iter().skip(1) {
+						let mut checked =
+							self.export.packable_presets.iter().any(|p| p.name() == name);
+						if ui.checkbox(&mut checked, name).changed() {
+							toggled = Some((name.clone(), checked));
+						}
+					}
+				});
 
-			if cbc {
-				self.export.packable_preset = None;
+			match toggled {
+				Some((name, false)) => {
+					self.export.packable_presets.retain(|p| p.name() != name);
+				},
+				Some((name, true)) => self.collect_presets(ui.ctx(), vec![name], None),
+				None => {},
+			}
+		});
 
-				if self.export.current_preset_selection > 0 {
-					self.export.e_name =
-						self.known_presets[self.export.current_preset_selection].clone();
+		ui.horizontal(|ui| {
+			ui.label("Include: ");
+			let previous = self.export.policy;
+			egui::ComboBox::from_id_source("kspack-export-collect-policy")
+				.selected_text(self.export.policy.to_string())
+				.show_ui(ui, |ui| {
+					for policy in CollectPolicy::ALL {
+						ui.selectable_value(&mut self.export.policy, policy, policy.to_string());
+					}
+				});
 
-					let packer = pack::packer::Packer::new(
-						&self.persisted.keysight_path,
-						self.current_ks_version.unwrap(),
-						&self.known_presets[self.export.current_preset_selection],
-					);
+			// the assets of the selected presets depend on the policy
+			if self.export.policy != previous && !self.export.packable_presets.is_empty() {
+				let names = self.export.packable_presets.iter().map(|p| p.name().to_owned()).collect();
+				self.collect_presets(ui.ctx(), names, Some(previous));
+			}
+		});
 
-					match packer.collect(true) {
-						Err(why) => self.current_error = Some(format_error!(why)),
-						Ok(preset) => self.export.packable_preset = Some(preset),
-					}
+		ui.horizontal(|ui| {
+			ui.checkbox(&mut self.export.optimise, "Optimise textures")
+				.on_hover_text("Recompress png textures losslessly and remove exif and icc metadata");
+			if self.export.optimise {
+				let mut downscale = self.export.max_size.is_some();
+				if ui.checkbox(&mut downscale, "Scale down to").changed() {
+					self.export.max_size = downscale.then_some(texture::RECOMMENDED_MAX_SIZE);
+				}
+				if let Some(max_size) = &mut self.export.max_size {
+					ui.add(egui::DragValue::new(max_size).clamp_range(1..=16384).suffix(" px"));
 				}
 			}
 		});
 
-		if let Some(ppreset) = self.export.packable_preset.as_ref() {
+		ui.horizontal(|ui| {
+			ui.label("Compression: ");
+			egui::ComboBox::from_id_source("kspack-export-compression")
+				.selected_text(self.export.compression.to_string())
+				.show_ui(ui, |ui| {
+					for profile in CompressionProfile::ALL {
+						ui.selectable_value(&mut self.export.compression, profile, profile.to_string());
+					}
+				});
+			ui.checkbox(&mut self.export.compatible, "Compatible")
+				.on_hover_text("Compress with deflate, so zip tools without zstd support can open the file");
+		});
+
+		if !self.export.packable_presets.is_empty() {
 			ui.separator();
 
 			egui::Grid::new("kspack-export-preset-select").num_columns(2).show(ui, |ui| {
@@ -507,14 +745,58 @@ impl App {
 				self.export.e_description = self.export.e_description.chars().take(512).collect();
 			}
 
-			if !ppreset.assets().is_empty() {
+			let presets = &self.export.packable_presets;
+			let is_bundle = presets.len() > 1;
+			if is_bundle {
+				ui.label("The presets are exported as a bundle, the name is the name of the bundle.");
+			}
+
+			// assets shared by several presets are only listed, and packed, once
+			let mut assets: Vec<_> = presets.iter().flat_map(|p| p.assets()).collect();
+			assets.sort_by(|a, b| a.path.cmp(&b.path));
+			assets.dedup_by(|a, b| a.path == b.path);
+
+			if !assets.is_empty() {
 				ui.label("The preset references the following assets that will be included:");
-				egui::Grid::new("kspack-export-found-assets").num_columns(2).show(ui, |ui| {
+				egui::Grid::new("kspack-export-found-assets").num_columns(3).show(ui, |ui| {
 					ui.label(RichText::new("File").strong().underline());
 					ui.label(RichText::new("Type").strong().underline());
+					ui.label(RichText::new("Status").strong().underline());
 					ui.end_row();
 
-					for asset in ppreset.assets() {
+					for asset in assets {
+						ui.label(format!("{}.{}", asset.name, asset.ext));
+						ui.label(format!("{:?}", asset.texture_type));
+						ui.label(if asset.active { "Enabled" } else { "Disabled" });
+						ui.end_row();
+
+						if !asset.candidates.is_empty() {
+							let others: Vec<String> = asset
+								.candidates
+								.iter()
+								.filter_map(|path| path.extension())
+								.map(|ext| format!("{}.{}", asset.name, ext.to_string_lossy()))
+								.collect();
+							ui.label(
+								RichText::new(format!("    also found {}, which is ignored", others.join(", ")))
+									.color(Color32::RED),
+							);
+							ui.end_row();
+						}
+
+						for warning in &asset.warnings {
+							ui.label(RichText::new(format!("    {}", warning)).color(Color32::RED));
+							ui.end_row();
+						}
+					}
+				});
+			}
+
+			let builtin: Vec<_> = presets.iter().flat_map(|p| p.builtin()).collect();
+			if !builtin.is_empty() {
+				ui.label("The following builtin assets are used, and not included:");
+				egui::Grid::new("kspack-export-builtin-assets").num_columns(2).show(ui, |ui| {
+					for asset in builtin {
 						ui.label(format!("{}.{}", asset.name, asset.ext));
 						ui.label(format!("{:?}", asset.texture_type));
 						ui.end_row();
@@ -522,12 +804,29 @@ impl App {
 				});
 			}
 
+			let missing: Vec<_> = presets.iter().flat_map(|p| p.missing()).collect();
+			if !missing.is_empty() {
+				ui.label(
+					RichText::new(
+						"Warning! The following assets could not be found.\n    They will show up \
+						 blank for anyone importing the preset.",
+					)
+					.color(Color32::RED),
+				);
+				egui::Grid::new("kspack-export-missing-assets").num_columns(2).show(ui, |ui| {
+					for asset in missing {
+						ui.label(RichText::new(&asset.name).color(Color32::RED));
+						ui.label(format!("{:?}", asset.texture_type));
+						ui.end_row();
+					}
+				});
+			}
+
 			if ui.button("Export").clicked() {
 				if let Some(path) = rfd::FileDialog::new().add_filter(PRESET_EXT_NAME, &[PRESET_EXT]).save_file() {
-					let result = ppreset.pack(&path, ExtraMeta {
+					let extra_meta = ExtraMeta {
 						rename:             if self.export.e_name.bytes().any(|v| !v.is_ascii_whitespace())
-							&& self.export.e_name
-								!= self.known_presets[self.export.current_preset_selection]
+							&& (is_bundle || self.export.e_name != presets[0].name())
 						{
 							Some(self.export.e_name.clone())
 						} else {
@@ -537,21 +836,379 @@ impl App {
 						description:        self.export.e_description.clone(),
 						version:            self.export.e_version,
 						current_ks_version: self.current_ks_version.unwrap(),
+					};
+					let options = PackOptions {
+						optimise:    self
+							.export
+							.optimise
+							.then_some(texture::Optimise { max_size: self.export.max_size }),
+						compression: self.export.compression,
+						compatible:  self.export.compatible,
+						..PackOptions::default()
+					};
+
+					let presets = std::mem::take(&mut self.export.packable_presets);
+					let label = format!("Exporting to {}", path.display());
+					self.task = Some(Task::spawn(ui.ctx(), label, move |progress| {
+						let options = PackOptions { progress: progress.clone(), ..options };
+						let result = if is_bundle {
+							PackablePreset::pack_bundle(&presets, &path, extra_meta, &options)
+						} else {
+							presets[0].pack(&path, extra_meta, &options)
+						};
+						TaskResult::Export { path, optimised: options.optimise.is_some(), presets, result }
+					}));
+				}
+			}
+		}
+	}
+
+	/// Shows the result of a finished task, restoring the state it took if it failed
+	fn finish_task(&mut self, task: Task) {
+		let result = match task.handle.join() {
+			Ok(result) => result,
+			Err(_) => {
+				self.status_message = Some(Message::Error {
+					message: format!("{} failed unexpectedly, see the log for details", task.label),
+				});
+				return;
+			},
+		};
+
+		match result {
+			TaskResult::Export { path, optimised, presets, result } => match result {
+				Ok(summary) => {
+					let mut message = format!("Exported preset to {}", path.display());
+					if optimised {
+						message += &format!(
+							"\n\nOptimising textures saved {} ({} to {})",
+							helpers::format_size(summary.saved()),
+							helpers::format_size(summary.original_size),
+							helpers::format_size(summary.packed_size)
+						);
+					}
+					if !summary.warnings.is_empty() {
+						message += "\n\nSome textures were packed with warnings:";
+						for warning in &summary.warnings {
+							let file = warning.path.file_name().unwrap_or_default().to_string_lossy();
+							message += &format!("\n{}: {}", file, warning.warning);
+						}
+					}
+					self.status_message = Some(Message::Success { message });
+					self.export = ExportState::default();
+				},
+				Err(why) => {
+					self.export.packable_presets = presets;
+					if !matches!(why, PackError::Cancelled) {
+						self.status_message = Some(Message::Error {
+							message: format!("Failed to export preset to {}:\n\n{:#?}", path.display(), why),
+						});
+					}
+				},
+			},
+			TaskResult::Import { bundle, result } => match result {
+				Ok(packs) => {
+					let names: Vec<&str> = packs.iter().map(|p| p.name()).collect();
+					self.status_message = Some(Message::Success {
+						message: format!("Successfully imported preset {}", names.join(", ")),
 					});
+					self.import = ImportState::default();
+					self.installed = None;
+				},
+				Err(why) => {
+					self.import.bundle = Some(bundle);
+					if !matches!(why, UnpackError::Cancelled) {
+						self.current_error = Some(format_error!(why));
+						self.import.error_confirmed = false;
+					}
+				},
+			},
+			// loading and collecting cannot stop early, cancelling them drops their result
+			TaskResult::Load { result } => match result {
+				_ if task.progress.is_cancelled() => {},
+				Ok(bundle) => {
+					self.import.selected = vec![true; bundle.presets().len()];
+					self.import.bundle = Some(bundle);
+				},
+				Err(why) => self.current_error = Some(format_error!(why)),
+			},
+			TaskResult::Collect { previous_policy, results } => {
+				if task.progress.is_cancelled() {
+					if let Some(policy) = previous_policy {
+						self.export.policy = policy;
+					}
+					return;
+				}
+
+				if previous_policy.is_some() {
+					self.export.packable_presets.clear();
+				}
+				for result in results {
+					match result {
+						Ok(preset) => {
+							if previous_policy.is_none() && self.export.packable_presets.is_empty() {
+								self.export.e_name = preset.name().to_owned();
+							}
+							self.export.packable_presets.push(preset);
+						},
+						Err(why) => self.current_error = Some(format_error!(why)),
+					}
+				}
+			},
+		}
+	}
+
+	/// Collects saved presets with the selected policy in the background, which probes and reads
+	/// the headers of their textures. They are added to the selection, or replace it if the
+	/// policy changed from `previous_policy`.
+	fn collect_presets(&mut self, ctx: &egui::Context, names: Vec<String>, previous_policy: Option<CollectPolicy>) {
+		let env = self.current_env.clone().unwrap();
+		let ks_version = self.current_ks_version.unwrap();
+		let policy = self.export.policy;
+		let label = format!("Collecting the assets of {}", names.join(", "));
+		self.task = Some(Task::spawn(ctx, label, move |_| {
+			let results = names
+				.iter()
+				.map(|name| Packer::new(env.clone(), ks_version, name.as_str()).policy(policy).collect(true))
+				.collect();
+			TaskResult::Collect { previous_policy, results }
+		}));
+	}
+
+	fn installed_ui(&mut self, ui: &mut egui::Ui) {
+		ui.heading("Installed Presets");
+
+		let env = match self.current_env.as_ref() {
+			Some(env) => env,
+			None => return,
+		};
+
+		if self.installed.is_none() {
+			match Registry::load(env) {
+				Ok(registry) => self.installed = Some(registry),
+				Err(why) => {
+					self.current_error = Some(format_error!(why));
+					return;
+				},
+			}
+		}
 
-                    match result {
-                        Ok(()) => {self.status_message = Some(Message::Success {
-                            message: format!("Exported preset to {}", path.display())
-                        });
-
-                                  self.export = ExportState::default();
-                        },
-                        Err(why) => self.status_message = Some(Message::Error {
-                            message: format!("Failed to export preset to {}:\n\n{:#?}", path.display(), why)
-                        })
-                    }
+		let registry = self.installed.as_mut().unwrap();
+		if registry.packs().is_empty() {
+			ui.label("No presets have been imported yet.");
+		}
+
+		let mut uninstall = None;
+		for pack in registry.packs() {
+			ui.separator();
+			ui.horizontal(|ui| {
+				ui.label(RichText::new(pack.name()).strong());
+				ui.label(format!(
+					"{:#X} by {}, installed on {}",
+					pack.metadata.preset_version,
+					pack.metadata.author,
+					pack.installed.format("%F %T")
+				));
+				if ui.button("Uninstall").clicked() {
+					uninstall = Some(pack.name().to_owned());
 				}
+			});
+
+			egui::CollapsingHeader::new(format!("Files ({})", pack.assets.len() + 1))
+				.id_source(("kspack-installed-files", pack.name()))
+				.show(ui, |ui| {
+					ui.label(registry.resolve(&pack.preset).display().to_string());
+					for asset in &pack.assets {
+						let path = registry.resolve(&asset.path).display().to_string();
+						if asset.written {
+							ui.label(path);
+						} else {
+							ui.label(format!("{} (existing)", path));
+						}
+					}
+				});
+		}
+
+		if let Some(name) = uninstall {
+			match registry.uninstall(&name).and_then(|removed| registry.save().map(|()| removed)) {
+				Ok(removed) => {
+					self.status_message = Some(Message::Success {
+						message: format!(
+							"Removed preset {} and {} file(s), kept {} file(s) used by other presets",
+							name,
+							removed.removed.len(),
+							removed.shared.len()
+						),
+					})
+				},
+				Err(why) => self.current_error = Some(format_error!(why)),
 			}
+
+			self.installed = None;
 		}
 	}
 }
+
+/// Shows the metadata and conflicts of a preset about to be imported, returns whether importing
+/// it overwrites existing files
+fn packed_preset_ui(ui: &mut egui::Ui, idx: usize, preset: &mut PackedFile, ks_version: Version) -> bool {
+	let meta = preset.metadata().clone();
+
+	ui.label("Loaded Preset:");
+	egui::Grid::new(("kspack-import-preset-info", idx)).num_columns(2).striped(true).show(ui, |ui| {
+		ui.wrap_text();
+
+		ui.label("Name");
+		ui.add(egui::Label::new(&meta.name).wrap(true));
+		ui.end_row();
+
+		ui.label("Version");
+		ui.label(format!("{:#X}", meta.preset_version));
+		ui.end_row();
+
+		ui.label("Keysight Version");
+		ui.label(RichText::new(format!("{:#X}", meta.target_version)).color(
+			if meta.target_version != ks_version { Color32::RED } else { Color32::BLACK },
+		));
+		ui.end_row();
+
+		ui.label("Author");
+		ui.add(egui::Label::new(&meta.author).wrap(true));
+		ui.end_row();
+
+		ui.label("Description");
+		ui.add(egui::Label::new(&meta.description).wrap(true));
+		ui.end_row();
+
+		ui.label("Packed on");
+		ui.label(meta.packed.format("%F %T").to_string());
+		ui.end_row();
+	});
+
+	if !meta.assets.is_empty() {
+		egui::CollapsingHeader::new(format!("Assets ({})", meta.assets.len()))
+			.id_source(("kspack-import-assets", idx))
+			.show(ui, |ui| {
+				egui::Grid::new(("kspack-import-asset-list", idx)).num_columns(4).striped(true).show(
+					ui,
+					|ui| {
+						ui.label(RichText::new("File").strong().underline());
+						ui.label(RichText::new("Type").strong().underline());
+						ui.label(RichText::new("Format").strong().underline());
+						ui.label(RichText::new("Image").strong().underline());
+						ui.end_row();
+
+						for entry in &meta.assets {
+							ui.label(format!("{}.{}", entry.name, entry.extension));
+							ui.label(format!("{:?}", entry.texture_type));
+							ui.label(entry.format.to_string());
+							match entry.image {
+								Some(image) if !image.is_power_of_two() || image.is_oversized() => {
+									let why = if image.is_oversized() { "oversized" } else { "not a power of two" };
+									ui.label(RichText::new(format!("{} ({})", image, why)).color(Color32::RED))
+								},
+								Some(image) => ui.label(image.to_string()),
+								None => ui.label("Unknown"),
+							};
+							ui.end_row();
+						}
+					},
+				);
+			});
+	}
+
+	let exists = preset.exists();
+	let overwrites = preset.conflicts().iter().any(|c| c.may_overwrite());
+
+	if exists || !preset.conflicts().is_empty() {
+		ui.separator();
+	}
+
+	if exists {
+		ui.label(
+			RichText::new(
+				"Warning! A preset already exists under this name.\n    Please make sure that you \
+				 really want to overwrite it.",
+			)
+			.color(Color32::RED),
+		);
+	}
+
+	if !preset.conflicts().is_empty() {
+		if preset.report().count(AssetStatus::Differing) > 0 {
+			ui.label(
+				RichText::new(
+					"Warning! This preset has conflicting assets.\n    Please choose what to do \
+					 with each of them!",
+				)
+				.color(Color32::RED),
+			);
+		}
+		ui.label("Existing Assets");
+		egui::Grid::new(("kspack-import-conflict-list", idx)).num_columns(5).striped(true).show(
+			ui,
+			|ui| {
+				ui.label(RichText::new("File").strong().underline());
+				ui.label(RichText::new("Type").strong().underline());
+				ui.label(RichText::new("Hash").strong().underline());
+				ui.label(RichText::new("Status").strong().underline());
+				ui.label(RichText::new("Action").strong().underline());
+				ui.end_row();
+
+				for (cidx, conflict) in preset.conflicts_mut().iter_mut().enumerate() {
+					let entry = &meta.assets[conflict.asset];
+					ui.label(format!("{}.{}", entry.name, entry.extension));
+					ui.label(format!("{:?}", entry.texture_type));
+					ui.label(format!("{}...", entry.hash.chars().take(16).collect::<String>()));
+					match conflict.status {
+						AssetStatus::Differing => ui.label(RichText::new("Differs").color(Color32::RED)),
+						_ => ui.label("Identical"),
+					};
+					egui::ComboBox::from_id_source(("kspack-import-conflict-action", idx, cidx))
+						.selected_text(conflict.resolution.to_string())
+						.show_ui(ui, |ui| {
+							for resolution in Resolution::ALL {
+								ui.selectable_value(
+									&mut conflict.resolution,
+									resolution,
+									resolution.to_string(),
+								);
+							}
+						});
+					ui.end_row();
+				}
+			},
+		);
+	}
+
+	overwrites || exists
+}
+
+/// Shows the progress of a running task, with a button to cancel it
+fn task_ui(ui: &mut egui::Ui, task: &Task) {
+	ui.vertical_centered(|ui| {
+		ui.label(RichText::new(&task.label).strong());
+
+		let latest = task.latest.lock().unwrap().clone();
+		match latest {
+			Some(update) => {
+				ui.add(egui::ProgressBar::new(update.fraction()).show_percentage());
+				ui.label(format!(
+					"{} ({} of {})",
+					update.current,
+					helpers::format_size(update.done),
+					helpers::format_size(update.total)
+				));
+			},
+			None => {
+				ui.add(egui::ProgressBar::new(0.0).animate(true));
+			},
+		}
+
+		if task.progress.is_cancelled() {
+			ui.label("Cancelling...");
+		} else if ui.button("Cancel").clicked() {
+			task.progress.cancel();
+		}
+	});
+}